@@ -1,3 +1,6 @@
+// The payloads are built with the encoder, see the `client` feature
+#![cfg_attr(not(feature = "client"), allow(unused_imports, dead_code))]
+
 use mollusk_svm::Mollusk;
 use mollusk_svm_bencher::MolluskComputeUnitBencher;
 use solana_sdk::account::Account;
@@ -5,175 +8,8 @@ use solana_sdk::instruction::Instruction;
 use solana_sdk::pubkey::Pubkey;
 extern crate alloc;
 use alloc::vec;
-
-// Import the encoder from the test module structure
-use std::collections::{BinaryHeap, HashMap};
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum HuffmanTree {
-    Leaf {
-        freq: u32,
-        byte: u8,
-    },
-    Node {
-        freq: u32,
-        left: Box<HuffmanTree>,
-        right: Box<HuffmanTree>,
-    },
-}
-
-impl HuffmanTree {
-    fn freq(&self) -> u32 {
-        match self {
-            HuffmanTree::Leaf { freq, .. } => *freq,
-            HuffmanTree::Node { freq, .. } => *freq,
-        }
-    }
-}
-
-impl Ord for HuffmanTree {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.freq().cmp(&self.freq()) // Reverse for min-heap
-    }
-}
-
-impl PartialOrd for HuffmanTree {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-pub struct HuffmanEncoder {
-    codes: HashMap<u8, (u32, u8)>, // byte -> (code, bit_length)
-    tree_bytes: Vec<u8>,           // Serialized tree for decoder
-}
-
-impl HuffmanEncoder {
-    pub fn new(input: &str) -> Self {
-        let frequencies = Self::calculate_frequencies(input);
-        let tree = Self::build_tree(&frequencies);
-        let mut codes = HashMap::new();
-
-        if frequencies.len() == 1 {
-            // Special case: single character
-            let byte = *frequencies.keys().next().unwrap();
-            codes.insert(byte, (0, 1));
-        } else {
-            Self::generate_codes(&tree, &mut codes, 0, 0);
-        }
-
-        let tree_bytes = Self::serialize_tree(&tree);
-
-        Self { codes, tree_bytes }
-    }
-
-    fn calculate_frequencies(input: &str) -> HashMap<u8, u32> {
-        let mut frequencies = HashMap::new();
-        for byte in input.bytes() {
-            *frequencies.entry(byte).or_insert(0) += 1;
-        }
-        frequencies
-    }
-
-    fn build_tree(frequencies: &HashMap<u8, u32>) -> HuffmanTree {
-        let mut heap = BinaryHeap::new();
-
-        // Create leaf nodes
-        for (&byte, &freq) in frequencies {
-            heap.push(HuffmanTree::Leaf { freq, byte });
-        }
-
-        // Build tree
-        while heap.len() > 1 {
-            let right = heap.pop().unwrap();
-            let left = heap.pop().unwrap();
-            let freq = left.freq() + right.freq();
-            heap.push(HuffmanTree::Node {
-                freq,
-                left: Box::new(left),
-                right: Box::new(right),
-            });
-        }
-
-        heap.pop().unwrap()
-    }
-
-    fn generate_codes(
-        tree: &HuffmanTree,
-        codes: &mut HashMap<u8, (u32, u8)>,
-        code: u32,
-        depth: u8,
-    ) {
-        match tree {
-            HuffmanTree::Leaf { byte, .. } => {
-                codes.insert(*byte, (code, depth.max(1)));
-            }
-            HuffmanTree::Node { left, right, .. } => {
-                Self::generate_codes(left, codes, code << 1, depth + 1);
-                Self::generate_codes(right, codes, (code << 1) | 1, depth + 1);
-            }
-        }
-    }
-
-    fn serialize_tree(tree: &HuffmanTree) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        Self::serialize_tree_recursive(tree, &mut bytes);
-        bytes
-    }
-
-    fn serialize_tree_recursive(tree: &HuffmanTree, bytes: &mut Vec<u8>) {
-        match tree {
-            HuffmanTree::Leaf { byte, .. } => {
-                bytes.push(1); // Leaf marker
-                bytes.push(*byte);
-            }
-            HuffmanTree::Node { left, right, .. } => {
-                bytes.push(0); // Internal node marker
-                Self::serialize_tree_recursive(left, bytes);
-                Self::serialize_tree_recursive(right, bytes);
-            }
-        }
-    }
-
-    pub fn encode(&self, input: &str) -> Vec<u8> {
-        let mut result = Vec::new();
-        let mut current_byte = 0u8;
-        let mut bit_count = 0u8;
-
-        // First, write the tree
-        result.extend_from_slice(&(self.tree_bytes.len() as u16).to_le_bytes());
-        result.extend_from_slice(&self.tree_bytes);
-
-        // Then encode the data
-        for byte in input.bytes() {
-            if let Some(&(code, bit_length)) = self.codes.get(&byte) {
-                for i in (0..bit_length).rev() {
-                    let bit = ((code >> i) & 1) as u8;
-                    current_byte |= bit << (7 - bit_count);
-                    bit_count += 1;
-
-                    if bit_count == 8 {
-                        result.push(current_byte);
-                        current_byte = 0;
-                        bit_count = 0;
-                    }
-                }
-            }
-        }
-
-        // Push remaining bits
-        if bit_count > 0 {
-            result.push(current_byte);
-        }
-
-        result
-    }
-}
-
-pub fn huffman_encode_url(url: &str) -> Vec<u8> {
-    let encoder = HuffmanEncoder::new(url);
-    encoder.encode(url)
-}
+#[cfg(feature = "client")]
+use solana_huffman_encoding_challenge::encoder::{huffman_encode_batch, huffman_encode_url};
 
 // Benchmark utilities
 pub const PROGRAM: Pubkey = Pubkey::new_from_array(solana_huffman_encoding_challenge::ID);
@@ -182,24 +18,9 @@ pub fn mollusk() -> Mollusk {
     Mollusk::new(&PROGRAM, "target/deploy/solana_huffman_encoding_challenge")
 }
 
-pub fn create_instruction_data(encoded_url: &[u8], original_size: u32) -> Vec<u8> {
-    let mut data = Vec::new();
-
-    // Format: [original_len: 1][tree_size: 2][tree_data][encoded_bits]
-    data.push(original_size as u8); // Single byte for original length
-
-    // Extract tree size from encoded data (first 4 bytes as u32, convert to u16)
-    let tree_size = u16::from_le_bytes([encoded_url[0], encoded_url[1]]);
-
-    // data.extend_from_slice(&tree_size.to_le_bytes()); // u16 tree size
-    data.push(tree_size as u8); // Single byte for tree size (assuming <= 255)
-    data.extend_from_slice(&encoded_url[2..]); // Rest of encoded data (tree + bits)
-    data
-}
-
+#[cfg(feature = "client")]
 fn benchmark_url(_mollusk: &Mollusk, url: &str) -> (Instruction, Vec<(Pubkey, Account)>) {
-    let encoded_url = huffman_encode_url(url);
-    let instruction_data = create_instruction_data(&encoded_url, url.len() as u32);
+    let instruction_data = huffman_encode_url(url);
 
     let ix_accounts = vec![];
     let ix = Instruction::new_with_bytes(PROGRAM, &instruction_data, ix_accounts);
@@ -207,6 +28,13 @@ fn benchmark_url(_mollusk: &Mollusk, url: &str) -> (Instruction, Vec<(Pubkey, Ac
     (ix, tx_accounts)
 }
 
+#[cfg(not(feature = "client"))]
+fn main() {
+    eprintln!("compute_units needs the encoder, run it with `--features bench-default`");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "client")]
 fn main() {
     let mollusk = mollusk();
 
@@ -226,15 +54,30 @@ fn main() {
 
     let mut results = vec![];
 
-    for url in test_urls {
-        let (ix, tx_accounts) = benchmark_url(&mollusk, &url);
+    for url in &test_urls {
+        let (ix, tx_accounts) = benchmark_url(&mollusk, url);
 
-        results.push((url, ix, tx_accounts));
+        results.push((*url, ix, tx_accounts));
     }
 
+    // All challenge URLs in one instruction, paying for a single tree
+    let batch_data = huffman_encode_batch(&test_urls);
+    let batch_ix = Instruction::new_with_bytes(PROGRAM, &batch_data, vec![]);
+    let batch_cu = mollusk
+        .process_instruction(&batch_ix, &[])
+        .compute_units_consumed;
+    results.push(("batch of all challenge URLs", batch_ix, vec![]));
+
     let mut bencher = MolluskComputeUnitBencher::new(mollusk);
     for (url, ix, tx_accounts) in &results {
         bencher = bencher.bench((url, ix, tx_accounts));
     }
     bencher.must_pass(true).out_dir("benches/").execute();
+
+    println!(
+        "Batch of {} URLs: {} CU, {} CU per URL amortised",
+        test_urls.len(),
+        batch_cu,
+        batch_cu / test_urls.len() as u64
+    );
 }
@@ -0,0 +1,881 @@
+use crate::instruction::{
+    checksum::url_checksum,
+    format::{
+        write_length, Header, FLAG_CHECKSUM, MARKER_EOS, MARKER_INTERNAL, MARKER_LEAF,
+        MAX_CODE_LEN, MODE_BATCH, MODE_CANONICAL, MODE_PACKED_TREE, MODE_PUBLISH_TABLE,
+        MODE_SHARED, MODE_STATIC, MODE_STORED, MODE_TREE,
+    },
+    static_table::{ESCAPE_SYMBOL, STATIC_TABLE},
+    utf8::validate_utf8,
+    MAX_URL_LEN,
+};
+use std::{
+    boxed::Box,
+    cmp::Ordering,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    vec,
+    vec::Vec,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HuffmanTree {
+    Leaf {
+        freq: u32,
+        byte: u8,
+    },
+    Eos {
+        freq: u32,
+    },
+    Node {
+        freq: u32,
+        left: Box<HuffmanTree>,
+        right: Box<HuffmanTree>,
+    },
+}
+
+impl HuffmanTree {
+    fn freq(&self) -> u32 {
+        match self {
+            HuffmanTree::Leaf { freq, .. } => *freq,
+            HuffmanTree::Eos { freq } => *freq,
+            HuffmanTree::Node { freq, .. } => *freq,
+        }
+    }
+
+    /// Length of the longest code, 0 for a lone leaf.
+    fn depth(&self) -> usize {
+        match self {
+            HuffmanTree::Node { left, right, .. } => 1 + left.depth().max(right.depth()),
+            _ => 0,
+        }
+    }
+
+    /// Appends every leaf, left to right.
+    fn collect_leaves(&self, leaves: &mut Vec<HuffmanTree>) {
+        match self {
+            HuffmanTree::Node { left, right, .. } => {
+                left.collect_leaves(leaves);
+                right.collect_leaves(leaves);
+            }
+            leaf => leaves.push(leaf.clone()),
+        }
+    }
+
+    /// Orders leaves by symbol, with the end-of-stream leaf last.
+    fn symbol_order(&self) -> usize {
+        match self {
+            HuffmanTree::Leaf { byte, .. } => *byte as usize,
+            _ => 256,
+        }
+    }
+}
+
+/// Subtree waiting in the construction heap. Equal frequencies are broken by
+/// `order`: leaves are pushed by ascending symbol and every merged node after
+/// them, so the same input always builds the same tree.
+struct HeapEntry {
+    order: usize,
+    tree: HuffmanTree,
+}
+
+impl HeapEntry {
+    fn key(&self) -> (u32, usize) {
+        (self.tree.freq(), self.order)
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key()) // Reverse for min-heap
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Reasons [`HuffmanEncoder::try_encode`] rejects an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// There is nothing to build a tree from.
+    EmptyInput,
+    /// The input is longer than [`MAX_URL_LEN`], the longest URL the program decodes.
+    InputTooLong,
+    /// The input is not UTF-8, which the program rejects once decoded.
+    InvalidUtf8,
+}
+
+/// Instruction data built by [`HuffmanEncoder::try_encode`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodedUrl {
+    bytes: Vec<u8>,
+}
+
+impl EncodedUrl {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// One symbol of an [`EncodingStats`] report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolStats {
+    pub byte: u8,
+    /// Occurrences in the encoded input.
+    pub freq: u32,
+    /// Code, in the low `bit_length` bits.
+    pub code: u32,
+    pub bit_length: u8,
+}
+
+/// Size breakdown of one encoding, see [`HuffmanEncoder::stats`] and
+/// [`huffman_encode_url_auto_stats`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodingStats {
+    /// Mode of the format byte, one of the `MODE_*` constants of [`format`].
+    ///
+    /// [`format`]: crate::instruction::format
+    pub mode: u8,
+    /// Symbols of the input, by byte value. [`MODE_STORED`] payloads store every
+    /// byte as itself, an 8-bit code.
+    pub symbols: Vec<SymbolStats>,
+    /// End-of-stream code and bit length, for trees built by
+    /// [`HuffmanEncoder::with_eos`].
+    pub eos_code: Option<(u32, u8)>,
+    pub header_bytes: usize,
+    pub tree_bytes: usize,
+    /// Bits of the coded symbols, including the end-of-stream code.
+    pub payload_bits: usize,
+    /// Zero bits filling the last payload byte.
+    pub padding_bits: usize,
+    /// Size of the whole instruction data.
+    pub total_bytes: usize,
+    /// Input length over `total_bytes`, above 1 when the encoding is smaller, the
+    /// "Compression Ratio" of the challenge metrics. `None` for the empty input,
+    /// which has nothing to compress.
+    pub compression_ratio: Option<f64>,
+}
+
+pub struct HuffmanEncoder {
+    codes: HashMap<u8, (u32, u8)>, // byte -> (code, bit_length)
+    eos_code: Option<(u32, u8)>,   // End-of-stream leaf, see `with_eos`
+    tree_bytes: Vec<u8>,           // Serialized tree for decoder
+}
+
+impl HuffmanEncoder {
+    /// Builds the Huffman tree of `input`, with codes of at most [`MAX_CODE_LEN`]
+    /// bits, the longest the decoder accepts.
+    pub fn new(input: &str) -> Self {
+        Self::build(input.as_bytes(), false, MAX_CODE_LEN)
+    }
+
+    /// Builds a tree with an extra end-of-stream leaf, so that `encode` can drop
+    /// the `original_len` header field. Only `encode` supports such trees.
+    pub fn with_eos(input: &str) -> Self {
+        Self::build(input.as_bytes(), true, MAX_CODE_LEN)
+    }
+
+    /// Same as [`HuffmanEncoder::new`], with codes of at most `max_code_len` bits.
+    ///
+    /// When the Huffman tree is deeper, the optimal length-limited code is used
+    /// instead, so payloads can target decoders with a fixed-width lookup table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_code_len` is 0, above 32, or too short to give every byte
+    /// of `input` its own code.
+    pub fn with_max_code_len(input: &str, max_code_len: usize) -> Self {
+        assert!(
+            (1..=32).contains(&max_code_len),
+            "max_code_len out of range"
+        );
+        Self::build(input.as_bytes(), false, max_code_len)
+    }
+
+    /// Encodes `input` with its own tree, using whichever of the `original_len`
+    /// field or an end-of-stream leaf gives the smaller payload, like
+    /// [`huffman_encode_url`], but rejects inputs the program cannot decode
+    /// instead of panicking.
+    pub fn try_encode(input: &[u8]) -> Result<EncodedUrl, EncodeError> {
+        if input.is_empty() {
+            return Err(EncodeError::EmptyInput);
+        }
+        if input.len() > MAX_URL_LEN {
+            return Err(EncodeError::InputTooLong);
+        }
+        if validate_utf8(input).is_err() {
+            return Err(EncodeError::InvalidUtf8);
+        }
+
+        // Both trees fit the decoder: `build` caps codes at `MAX_CODE_LEN`, and UTF-8
+        // has at most 243 distinct bytes, so even with the end-of-stream leaf the
+        // tree stays well under `MAX_TREE_NODES`.
+        let mut bytes = Self::build(input, false, MAX_CODE_LEN).encode_bytes(input);
+        let eos_bytes = Self::build(input, true, MAX_CODE_LEN).encode_bytes(input);
+        if eos_bytes.len() < bytes.len() {
+            bytes = eos_bytes;
+        }
+
+        Ok(EncodedUrl { bytes })
+    }
+
+    fn build(input: &[u8], eos: bool, max_code_len: usize) -> Self {
+        let frequencies = Self::calculate_frequencies(input);
+        let mut tree = Self::build_tree(&frequencies, eos);
+        if tree.depth() > max_code_len {
+            tree = Self::build_length_limited_tree(&tree, max_code_len);
+        }
+        let mut codes = HashMap::new();
+        let mut eos_code = None;
+
+        if frequencies.len() == 1 && !eos {
+            // Single character case
+            let byte = *frequencies.keys().next().unwrap();
+            codes.insert(byte, (0, 1));
+        } else {
+            Self::generate_codes(&tree, &mut codes, &mut eos_code, 0, 0);
+        }
+
+        let tree_bytes = Self::serialize_tree(&tree);
+        Self {
+            codes,
+            eos_code,
+            tree_bytes,
+        }
+    }
+
+    fn calculate_frequencies(input: &[u8]) -> BTreeMap<u8, u32> {
+        let mut frequencies = BTreeMap::new();
+        for &byte in input {
+            *frequencies.entry(byte).or_insert(0) += 1;
+        }
+        frequencies
+    }
+
+    fn build_tree(frequencies: &BTreeMap<u8, u32>, eos: bool) -> HuffmanTree {
+        let mut heap = BinaryHeap::new();
+
+        // Create leaf nodes, in symbol order
+        for (&byte, &freq) in frequencies {
+            let order = heap.len();
+            heap.push(HeapEntry {
+                order,
+                tree: HuffmanTree::Leaf { freq, byte },
+            });
+        }
+        if eos {
+            // Read once per URL
+            let order = heap.len();
+            heap.push(HeapEntry {
+                order,
+                tree: HuffmanTree::Eos { freq: 1 },
+            });
+        }
+
+        // Build tree, merged nodes tie after every node pushed before them
+        let mut order = heap.len();
+        while heap.len() > 1 {
+            let right = heap.pop().unwrap().tree;
+            let left = heap.pop().unwrap().tree;
+            let freq = left.freq() + right.freq();
+            heap.push(HeapEntry {
+                order,
+                tree: HuffmanTree::Node {
+                    freq,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            });
+            order += 1;
+        }
+
+        heap.pop().unwrap().tree
+    }
+
+    /// Rebuilds `tree` with the same leaves and codes of at most `max_code_len`
+    /// bits, using package-merge for the code lengths and canonical codes for the
+    /// shape.
+    fn build_length_limited_tree(tree: &HuffmanTree, max_code_len: usize) -> HuffmanTree {
+        let mut leaves = Vec::new();
+        tree.collect_leaves(&mut leaves);
+        assert!(
+            leaves.len() <= 1 << max_code_len,
+            "max_code_len too short for the number of symbols"
+        );
+        leaves.sort_by_key(|leaf| (leaf.freq(), leaf.symbol_order()));
+
+        let weights: Vec<u32> = leaves.iter().map(HuffmanTree::freq).collect();
+        let lengths = Self::package_merge(&weights, max_code_len);
+
+        let mut leaves: Vec<(u8, HuffmanTree)> = lengths.into_iter().zip(leaves).collect();
+        leaves.sort_by_key(|(bit_length, leaf)| (*bit_length, leaf.symbol_order()));
+
+        // Canonical codes, as in `canonical_codes`
+        let mut coded = Vec::with_capacity(leaves.len());
+        let mut code = 0u32;
+        let mut prev_len = leaves[0].0;
+        for (bit_length, leaf) in leaves {
+            code <<= bit_length - prev_len;
+            coded.push((code, bit_length, leaf));
+            code += 1;
+            prev_len = bit_length;
+        }
+        Self::canonical_tree(&coded, 0)
+    }
+
+    /// Code lengths of the optimal prefix code with lengths of at most `max_len`,
+    /// for `weights` sorted in ascending order and at least two of them.
+    fn package_merge(weights: &[u32], max_len: usize) -> Vec<u8> {
+        let leaves: Vec<(u64, Vec<usize>)> = weights
+            .iter()
+            .enumerate()
+            .map(|(i, &weight)| (weight as u64, vec![i]))
+            .collect();
+
+        let mut items = leaves.clone();
+        for _ in 1..max_len {
+            let packages = items
+                .chunks_exact(2)
+                .map(|pair| (pair[0].0 + pair[1].0, [&pair[0].1[..], &pair[1].1].concat()));
+            let mut merged = leaves.clone();
+            merged.extend(packages);
+            // Stable, so leaves come before packages of the same weight
+            merged.sort_by_key(|(weight, _)| *weight);
+            items = merged;
+        }
+
+        // Every selected item holding a symbol adds one bit to its code
+        let mut lengths = vec![0u8; weights.len()];
+        for (_, symbols) in &items[..2 * weights.len() - 2] {
+            for &symbol in symbols {
+                lengths[symbol] += 1;
+            }
+        }
+        lengths
+    }
+
+    /// Tree of `(code, bit_length, leaf)` entries sorted by code, all of which
+    /// share their first `depth` bits.
+    fn canonical_tree(leaves: &[(u32, u8, HuffmanTree)], depth: u8) -> HuffmanTree {
+        if let [(_, bit_length, leaf)] = leaves {
+            if *bit_length == depth {
+                return leaf.clone();
+            }
+        }
+
+        let split = leaves
+            .partition_point(|(code, bit_length, _)| (code >> (bit_length - depth - 1)) & 1 == 0);
+        let left = Self::canonical_tree(&leaves[..split], depth + 1);
+        let right = Self::canonical_tree(&leaves[split..], depth + 1);
+        HuffmanTree::Node {
+            freq: left.freq() + right.freq(),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn generate_codes(
+        tree: &HuffmanTree,
+        codes: &mut HashMap<u8, (u32, u8)>,
+        eos_code: &mut Option<(u32, u8)>,
+        code: u32,
+        depth: u8,
+    ) {
+        match tree {
+            HuffmanTree::Leaf { byte, .. } => {
+                codes.insert(*byte, (code, depth.max(1)));
+            }
+            HuffmanTree::Eos { .. } => {
+                *eos_code = Some((code, depth.max(1)));
+            }
+            HuffmanTree::Node { left, right, .. } => {
+                Self::generate_codes(left, codes, eos_code, code << 1, depth + 1);
+                Self::generate_codes(right, codes, eos_code, (code << 1) | 1, depth + 1);
+            }
+        }
+    }
+
+    fn serialize_tree(tree: &HuffmanTree) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        Self::serialize_tree_recursive(tree, &mut bytes);
+        bytes
+    }
+
+    fn serialize_tree_recursive(tree: &HuffmanTree, bytes: &mut Vec<u8>) {
+        match tree {
+            HuffmanTree::Leaf { byte, .. } => {
+                bytes.push(MARKER_LEAF);
+                bytes.push(*byte);
+            }
+            HuffmanTree::Eos { .. } => {
+                bytes.push(MARKER_EOS);
+            }
+            HuffmanTree::Node { left, right, .. } => {
+                bytes.push(MARKER_INTERNAL);
+                Self::serialize_tree_recursive(left, bytes);
+                Self::serialize_tree_recursive(right, bytes);
+            }
+        }
+    }
+
+    /// Encodes `input` after the serialized tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` has a byte missing from the tree or is longer than
+    /// [`MAX_URL_LEN`], as do the other `encode_*` methods.
+    pub fn encode(&self, input: &str) -> Vec<u8> {
+        self.encode_bytes(input.as_bytes())
+    }
+
+    fn encode_bytes(&self, input: &[u8]) -> Vec<u8> {
+        assert_url_len(input.len());
+        let mut result = Vec::new();
+
+        // First, write the header and the tree
+        let header = match self.eos_code {
+            Some(_) => Header::with_eos(self.tree_bytes.len()),
+            None => Header::new(input.len(), self.tree_bytes.len()),
+        };
+        result.resize(header.encoded_len(), 0);
+        header.write(&mut result);
+        result.extend_from_slice(&self.tree_bytes);
+
+        // Then encode the data, ending with the end-of-stream code if there is one
+        let codes = input.iter().map(|byte| self.codes[byte]);
+        Self::write_codes(codes.chain(self.eos_code), &mut result);
+        result
+    }
+
+    /// Reports how [`HuffmanEncoder::encode`] spends the bytes of `input`'s encoding.
+    /// This is the [`MODE_TREE`] layout only, see [`huffman_encode_url_auto_stats`]
+    /// for the format auto mode picks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` has a byte missing from the tree.
+    pub fn stats(&self, input: &str) -> EncodingStats {
+        let header = match self.eos_code {
+            Some(_) => Header::with_eos(self.tree_bytes.len()),
+            None => Header::new(input.len(), self.tree_bytes.len()),
+        };
+        Self::layout_stats(
+            MODE_TREE,
+            input.as_bytes(),
+            &self.codes,
+            self.eos_code,
+            header.encoded_len(),
+            self.tree_bytes.len(),
+        )
+    }
+
+    /// Size breakdown of `input` coded with `codes`, then `eos_code` if any, after
+    /// `header_bytes` of header and `tree_bytes` describing the code.
+    fn layout_stats(
+        mode: u8,
+        input: &[u8],
+        codes: &HashMap<u8, (u32, u8)>,
+        eos_code: Option<(u32, u8)>,
+        header_bytes: usize,
+        tree_bytes: usize,
+    ) -> EncodingStats {
+        let frequencies = Self::calculate_frequencies(input);
+        let symbols: Vec<SymbolStats> = frequencies
+            .iter()
+            .map(|(&byte, &freq)| {
+                let (code, bit_length) = codes[&byte];
+                SymbolStats {
+                    byte,
+                    freq,
+                    code,
+                    bit_length,
+                }
+            })
+            .collect();
+
+        let payload_bits = symbols
+            .iter()
+            .map(|symbol| symbol.freq as usize * symbol.bit_length as usize)
+            .chain(eos_code.map(|(_, bit_length)| bit_length as usize))
+            .sum::<usize>();
+        let payload_bytes = payload_bits.div_ceil(8);
+        let total_bytes = header_bytes + tree_bytes + payload_bytes;
+
+        EncodingStats {
+            mode,
+            symbols,
+            eos_code,
+            header_bytes,
+            tree_bytes,
+            payload_bits,
+            padding_bits: payload_bytes * 8 - payload_bits,
+            total_bytes,
+            compression_ratio: (!input.is_empty()).then(|| input.len() as f64 / total_bytes as f64),
+        }
+    }
+
+    /// Encodes `input` with the tree packed at the bit level: a marker bit per node
+    /// and 8 bits per leaf symbol, padded with zero bits to a whole byte.
+    pub fn encode_packed(&self, input: &str) -> Vec<u8> {
+        assert_url_len(input.len());
+        let tree = self.packed_tree_bytes();
+
+        let mut result = Vec::new();
+        let header = Header::with_mode(MODE_PACKED_TREE, input.len(), tree.len());
+        result.resize(header.encoded_len(), 0);
+        header.write(&mut result);
+        result.extend_from_slice(&tree);
+
+        Self::write_bits(&self.codes, input, &mut result);
+        result
+    }
+
+    /// Repacks the byte serialized tree, whose markers are whole bytes.
+    fn packed_tree_bytes(&self) -> Vec<u8> {
+        let mut bits = Vec::new();
+        let mut bytes = self.tree_bytes.iter();
+        while let Some(&marker) = bytes.next() {
+            bits.push(marker);
+            if marker == 1 {
+                let byte = *bytes.next().unwrap();
+                bits.extend((0..8).rev().map(|i| (byte >> i) & 1));
+            }
+        }
+
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, &bit)| byte | (bit << (7 - i)))
+            })
+            .collect()
+    }
+
+    /// Encodes `input` against this encoder's tree once it is published as a shared
+    /// table, so the tree should come from a corpus covering `input`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` has a byte missing from the tree.
+    pub fn encode_shared(&self, input: &str) -> Vec<u8> {
+        assert_url_len(input.len());
+        let mut result = Vec::new();
+        let header = Header::with_mode(MODE_SHARED, input.len(), 0);
+        result.resize(header.encoded_len(), 0);
+        header.write(&mut result);
+
+        Self::write_bits(&self.codes, input, &mut result);
+        result
+    }
+
+    /// Encodes every input as one batch record after this encoder's tree, which must
+    /// cover the bytes of all of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than 255 inputs, or if an input has a byte missing
+    /// from the tree or is longer than [`MAX_URL_LEN`].
+    pub fn encode_batch(&self, inputs: &[&str]) -> Vec<u8> {
+        let count = u8::try_from(inputs.len()).expect("too many URLs for one batch");
+        let max_len = inputs.iter().map(|input| input.len()).max().unwrap_or(0);
+        assert_url_len(max_len);
+
+        let mut result = Vec::new();
+        let header = Header::with_mode(MODE_BATCH, max_len, self.tree_bytes.len());
+        result.resize(header.encoded_len(), 0);
+        header.write(&mut result);
+        result.extend_from_slice(&self.tree_bytes);
+
+        result.push(count);
+        for input in inputs {
+            let mut len = [0u8; 2];
+            let len_size = write_length(&mut len, input.len(), header.is_wide());
+            result.extend_from_slice(&len[..len_size]);
+            Self::write_bits(&self.codes, input, &mut result);
+        }
+        result
+    }
+
+    /// Instruction data publishing this encoder's tree as shared table `table_id`.
+    pub fn publish_table(&self, table_id: u32, bump: u8) -> Vec<u8> {
+        let mut result = vec![MODE_PUBLISH_TABLE];
+        result.extend_from_slice(&table_id.to_le_bytes());
+        result.push(bump);
+        result.extend_from_slice(&self.tree_bytes);
+        result
+    }
+
+    /// Encodes `input` with canonical codes, sending only the code lengths.
+    ///
+    /// Returns `None` when more than 255 symbols share a code length, which the
+    /// one byte per length counts cannot describe.
+    pub fn encode_canonical(&self, input: &str) -> Option<Vec<u8>> {
+        assert_url_len(input.len());
+        let lengths = self.canonical_lengths();
+        let table = Self::canonical_table(&lengths)?;
+        let codes = Self::canonical_codes(&lengths);
+
+        let mut result = Vec::new();
+        let header = Header::with_mode(MODE_CANONICAL, input.len(), table.len());
+        result.resize(header.encoded_len(), 0);
+        header.write(&mut result);
+        result.extend_from_slice(&table);
+
+        Self::write_bits(&codes, input, &mut result);
+        Some(result)
+    }
+
+    /// (bit_length, byte) pairs sorted by length, then by byte value.
+    fn canonical_lengths(&self) -> Vec<(u8, u8)> {
+        let mut lengths: Vec<(u8, u8)> = self
+            .codes
+            .iter()
+            .map(|(&byte, &(_, bit_length))| (bit_length, byte))
+            .collect();
+        lengths.sort_unstable();
+        lengths
+    }
+
+    fn canonical_table(lengths: &[(u8, u8)]) -> Option<Vec<u8>> {
+        let max_len = lengths.last()?.0 as usize;
+        let mut counts = vec![0usize; max_len];
+        for &(bit_length, _) in lengths {
+            counts[bit_length as usize - 1] += 1;
+        }
+        if counts.iter().any(|&count| count > u8::MAX as usize) {
+            return None;
+        }
+
+        let mut table = vec![max_len as u8];
+        table.extend(counts.iter().map(|&count| count as u8));
+        table.extend(lengths.iter().map(|&(_, byte)| byte));
+        Some(table)
+    }
+
+    fn canonical_codes(lengths: &[(u8, u8)]) -> HashMap<u8, (u32, u8)> {
+        let mut codes = HashMap::new();
+        let mut code = 0u32;
+        let mut prev_len = lengths[0].0;
+        for &(bit_length, byte) in lengths {
+            code <<= bit_length - prev_len;
+            codes.insert(byte, (code, bit_length));
+            code += 1;
+            prev_len = bit_length;
+        }
+        codes
+    }
+
+    fn write_bits(codes: &HashMap<u8, (u32, u8)>, input: &str, result: &mut Vec<u8>) {
+        let codes = input.bytes().map(|byte| codes[&byte]);
+        Self::write_codes(codes, result);
+    }
+
+    /// Appends `(code, bit_length)` pairs MSB first, padding the last byte with zeros.
+    fn write_codes(codes: impl IntoIterator<Item = (u32, u8)>, result: &mut Vec<u8>) {
+        let mut current_byte = 0u8;
+        let mut bit_count = 0u8;
+
+        for (code, bit_length) in codes {
+            for i in (0..bit_length).rev() {
+                let bit = ((code >> i) & 1) as u8;
+                current_byte |= bit << (7 - bit_count);
+                bit_count += 1;
+
+                if bit_count == 8 {
+                    result.push(current_byte);
+                    current_byte = 0;
+                    bit_count = 0;
+                }
+            }
+        }
+
+        // Push remaining bits
+        if bit_count > 0 {
+            result.push(current_byte);
+        }
+    }
+}
+
+/// Every encoder refuses URLs the program would reject rather than emit them.
+fn assert_url_len(len: usize) {
+    assert!(len <= MAX_URL_LEN, "URL longer than MAX_URL_LEN");
+}
+
+/// Encodes `url` with its own tree, using whichever of the `original_len` field
+/// or an end-of-stream leaf gives the smaller payload.
+pub fn huffman_encode_url(url: &str) -> Vec<u8> {
+    let length_prefixed = huffman_encode_url_length_prefixed(url);
+    let eos = HuffmanEncoder::with_eos(url).encode(url);
+    if eos.len() < length_prefixed.len() {
+        eos
+    } else {
+        length_prefixed
+    }
+}
+
+/// Encodes `url` in every format the program decodes on its own, the dynamic
+/// tree (see [`huffman_encode_url`]), the packed tree, canonical code lengths,
+/// the static table and stored bytes, and returns the smallest. Ties go to the
+/// earlier format in that list. The mode is in the format byte, which the program
+/// dispatches on.
+///
+/// Thanks to the stored format the result is never more than one byte larger
+/// than `url`. The tree formats are skipped for the empty URL, which has no tree.
+pub fn huffman_encode_url_auto(url: &str) -> Vec<u8> {
+    let has_tree = !url.is_empty();
+    let candidates = [
+        has_tree.then(|| huffman_encode_url(url)),
+        has_tree.then(|| huffman_encode_url_packed(url)),
+        has_tree
+            .then(|| huffman_encode_url_canonical(url))
+            .flatten(),
+        Some(huffman_encode_url_static(url)),
+        Some(huffman_encode_url_stored(url)),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .min_by_key(Vec::len)
+        .unwrap()
+}
+
+/// Reports how [`huffman_encode_url_auto`] spends the bytes of `url`'s encoding,
+/// whichever format it picks. [`EncodingStats::mode`] tells which one it is.
+pub fn huffman_encode_url_auto_stats(url: &str) -> EncodingStats {
+    let instruction_data = huffman_encode_url_auto(url);
+    let (header, _) = Header::parse(&instruction_data).unwrap();
+    let input = url.as_bytes();
+    let header_bytes = header.encoded_len();
+
+    match header.mode() {
+        MODE_TREE if header.is_eos() => HuffmanEncoder::with_eos(url).stats(url),
+        MODE_TREE => HuffmanEncoder::new(url).stats(url),
+        MODE_PACKED_TREE => {
+            let encoder = HuffmanEncoder::new(url);
+            HuffmanEncoder::layout_stats(
+                MODE_PACKED_TREE,
+                input,
+                &encoder.codes,
+                None,
+                header_bytes,
+                header.tree_size,
+            )
+        }
+        MODE_CANONICAL => {
+            let encoder = HuffmanEncoder::new(url);
+            let codes = HuffmanEncoder::canonical_codes(&encoder.canonical_lengths());
+            HuffmanEncoder::layout_stats(
+                MODE_CANONICAL,
+                input,
+                &codes,
+                None,
+                header_bytes,
+                header.tree_size,
+            )
+        }
+        MODE_STATIC => {
+            HuffmanEncoder::layout_stats(MODE_STATIC, input, &static_codes(), None, header_bytes, 0)
+        }
+        mode => {
+            debug_assert_eq!(mode, MODE_STORED);
+            let codes = (0..=u8::MAX).map(|byte| (byte, (byte as u32, 8))).collect();
+            HuffmanEncoder::layout_stats(MODE_STORED, input, &codes, None, header_bytes, 0)
+        }
+    }
+}
+
+pub fn huffman_encode_url_length_prefixed(url: &str) -> Vec<u8> {
+    let encoder = HuffmanEncoder::new(url);
+    encoder.encode(url)
+}
+
+pub fn huffman_encode_url_packed(url: &str) -> Vec<u8> {
+    let encoder = HuffmanEncoder::new(url);
+    encoder.encode_packed(url)
+}
+
+pub fn huffman_encode_url_canonical(url: &str) -> Option<Vec<u8>> {
+    let encoder = HuffmanEncoder::new(url);
+    encoder.encode_canonical(url)
+}
+
+/// Encodes `urls` as one batch, with a single tree built from all of them.
+///
+/// # Panics
+///
+/// Panics if `urls` holds no bytes at all, e.g. is empty, since there is no tree
+/// to build, and otherwise as [`HuffmanEncoder::encode_batch`] does.
+pub fn huffman_encode_batch(urls: &[&str]) -> Vec<u8> {
+    let encoder = HuffmanEncoder::new(&urls.concat());
+    encoder.encode_batch(urls)
+}
+
+/// Codes of the program's built-in table, assigned exactly as the decoder does.
+///
+/// Every byte missing from the table, and `ESCAPE_SYMBOL` itself, maps to the
+/// escape code followed by the byte as an 8-bit literal.
+fn static_codes() -> HashMap<u8, (u32, u8)> {
+    let max_len = STATIC_TABLE[0] as usize;
+    let (counts, symbols) = STATIC_TABLE[1..].split_at(max_len);
+
+    let mut symbols = symbols.iter();
+    let mut lengths = Vec::new();
+    for (i, &count) in counts.iter().enumerate() {
+        for _ in 0..count {
+            lengths.push((i as u8 + 1, *symbols.next().unwrap()));
+        }
+    }
+    let mut codes = HuffmanEncoder::canonical_codes(&lengths);
+
+    let (escape_code, escape_len) = codes[&ESCAPE_SYMBOL];
+    for byte in 0..=u8::MAX {
+        if byte == ESCAPE_SYMBOL || !codes.contains_key(&byte) {
+            codes.insert(byte, ((escape_code << 8) | byte as u32, escape_len + 8));
+        }
+    }
+    codes
+}
+
+/// Encodes `url` with the static table, escaping bytes the table lacks.
+pub fn huffman_encode_url_static(url: &str) -> Vec<u8> {
+    assert_url_len(url.len());
+    let codes = static_codes();
+
+    let mut result = Vec::new();
+    let header = Header::with_mode(MODE_STATIC, url.len(), 0);
+    result.resize(header.encoded_len(), 0);
+    header.write(&mut result);
+
+    HuffmanEncoder::write_bits(&codes, url, &mut result);
+    result
+}
+
+/// Sends `url` uncompressed.
+pub fn huffman_encode_url_stored(url: &str) -> Vec<u8> {
+    assert_url_len(url.len());
+    let mut result = vec![MODE_STORED];
+    result.extend_from_slice(url.as_bytes());
+    result
+}
+
+/// Flags `instruction_data` as carrying a checksum of `url` and appends it.
+pub fn with_checksum(mut instruction_data: Vec<u8>, url: &str) -> Vec<u8> {
+    instruction_data[0] |= FLAG_CHECKSUM;
+    instruction_data.extend_from_slice(&url_checksum(url.as_bytes()));
+    instruction_data
+}
@@ -1,25 +1,211 @@
 use pinocchio::{
-    entrypoint::InstructionContext, lazy_program_entrypoint, log::sol_log, no_allocator,
-    nostd_panic_handler, ProgramResult,
+    account_info::AccountInfo,
+    entrypoint::{InstructionContext, MaybeAccount},
+    instruction::Signer,
+    lazy_program_entrypoint, no_allocator, nostd_panic_handler,
+    program_error::ProgramError,
+    pubkey::create_program_address,
+    seeds,
+    sysvars::{rent::Rent, Sysvar},
+    ProgramResult,
 };
+use pinocchio_system::instructions::{Allocate, Assign, CreateAccount, Transfer};
 
-use crate::instruction::decoder::huffman_decode_url;
+use crate::instruction::{
+    batch::huffman_decode_batch,
+    decoder::{stored_url, try_huffman_decode_url_strict, DecodeOptions},
+    format::{MODE_BATCH, MODE_MASK, MODE_STORED},
+    shared_table::{
+        shared_table_len, try_huffman_decode_url_with_table, write_shared_table, PublishTable,
+        SharedTable, TABLE_SEED,
+    },
+    utf8::validate_utf8,
+};
+#[cfg(feature = "unchecked-decode")]
+use crate::instruction::{
+    decoder::{huffman_decode_url, SMALL_TREE_NODES},
+    format::{Header, FLAG_WIDE, MODE_TREE},
+};
+
+/// Output capacity of the unchecked path, the challenge's original 128 bytes so
+/// the benchmark stays comparable. Longer URLs are decoded checked.
+#[cfg(feature = "unchecked-decode")]
+const UNCHECKED_URL_LEN: usize = 128;
 
 lazy_program_entrypoint!(process_instruction);
 no_allocator!();
 nostd_panic_handler!();
 
+/// The lazy entrypoint only exposes the instruction data once every account is
+/// read, so the instruction is picked by its account count:
+///
+/// - no accounts: decode a payload carrying its own code, or a `MODE_BATCH` payload,
+///   whichever mode its format byte names
+/// - one account: decode a `MODE_SHARED` payload with that table account
+/// - three accounts: publish a shared table, see [`PublishTable`]
+#[inline(always)]
+fn process_instruction(mut context: InstructionContext) -> ProgramResult {
+    match context.remaining() {
+        0 => {
+            let instruction_data = unsafe { context.instruction_data_unchecked() };
+            decode_url(instruction_data)
+        }
+        1 => {
+            let table = next_account(&mut context)?;
+            decode_url_with_table(&table, context.instruction_data()?)
+        }
+        3 => {
+            let authority = next_account(&mut context)?;
+            let table = next_account(&mut context)?;
+            let _system_program = next_account(&mut context)?;
+            publish_table(&authority, &table, context.instruction_data()?)
+        }
+        _ => Err(ProgramError::InvalidArgument),
+    }
+}
+
 #[inline(always)]
-fn process_instruction(context: InstructionContext) -> ProgramResult {
-    let instruction_data = unsafe { context.instruction_data_unchecked() };
+fn next_account(context: &mut InstructionContext) -> Result<AccountInfo, ProgramError> {
+    match context.next_account()? {
+        MaybeAccount::Account(account) => Ok(account),
+        MaybeAccount::Duplicated(_) => Err(ProgramError::InvalidArgument),
+    }
+}
+
+#[inline(always)]
+fn decode_url(instruction_data: &[u8]) -> ProgramResult {
+    match instruction_data.first().map(|format| format & MODE_MASK) {
+        Some(MODE_BATCH) => return decode_batch(instruction_data),
+        // Checked in place, with or without `unchecked-decode`
+        Some(MODE_STORED) => return decode_stored(instruction_data),
+        _ => {}
+    }
+
+    // Opt-in unchecked path, only sound for trusted payloads (CU benchmarking). It
+    // only knows plain `MODE_TREE` payloads that fit its output and its 64 entry
+    // node table, everything else is decoded checked. Every node takes at least one
+    // tree byte, so a tree of at most 64 bytes has at most 64 nodes.
+    #[cfg(feature = "unchecked-decode")]
+    if let Ok((header, _)) = Header::parse(instruction_data) {
+        if header.format & !FLAG_WIDE == MODE_TREE
+            && header.original_len <= UNCHECKED_URL_LEN
+            && header.tree_size <= SMALL_TREE_NODES
+        {
+            let (_decoded_len, _decoded_bytes) =
+                unsafe { huffman_decode_url::<UNCHECKED_URL_LEN>(instruction_data) };
+            return Ok(());
+        }
+    }
 
-    let (_decoded_len, _decoded_bytes) = unsafe { huffman_decode_url(instruction_data) };
+    // Also checks the checksum trailer, if any, with the `sol_sha256` syscall. The
+    // zeroed output costs a single memset; handing the decoders uninitialised
+    // memory as `&mut [u8]` would be undefined behaviour.
+    let decoded = try_huffman_decode_url_strict(instruction_data)?;
+    let _url = decoded.as_str()?;
 
     // For validation - uncomment to log decoded URL (comment out for CU measurement)
-    // let res_str = unsafe {
-    //     core::str::from_utf8_unchecked(_decoded_bytes.get_unchecked(0..))
-    // };
-    // sol_log(&res_str);
+    // pinocchio::log::sol_log(_url);
+
+    Ok(())
+}
+
+#[inline(always)]
+fn decode_stored(instruction_data: &[u8]) -> ProgramResult {
+    let _url = validate_utf8(stored_url(instruction_data)?)?;
+
+    // pinocchio::log::sol_log(_url);
+
+    Ok(())
+}
+
+#[inline(always)]
+fn decode_batch(instruction_data: &[u8]) -> ProgramResult {
+    huffman_decode_batch(instruction_data, DecodeOptions::STRICT, |url| {
+        let _url = validate_utf8(url)?;
+
+        // pinocchio::log::sol_log(_url);
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[inline(always)]
+fn decode_url_with_table(table: &AccountInfo, instruction_data: &[u8]) -> ProgramResult {
+    if !table.is_owned_by(&crate::ID) {
+        return Err(ProgramError::IllegalOwner);
+    }
+    let data = table.try_borrow_data()?;
+    let table = SharedTable::from_account_data(&data)?;
+
+    let decoded =
+        try_huffman_decode_url_with_table(instruction_data, &table, DecodeOptions::STRICT)?;
+    let _url = decoded.as_str()?;
+
+    // pinocchio::log::sol_log(_url);
+
+    Ok(())
+}
+
+#[inline(always)]
+fn publish_table(
+    authority: &AccountInfo,
+    table: &AccountInfo,
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let publish = PublishTable::parse(instruction_data)?;
+    if !authority.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let table_id = publish.table_id.to_le_bytes();
+    let bump = [publish.bump];
+    let seeds = seeds!(TABLE_SEED, authority.key(), &table_id, &bump);
+    let seed_slices = [TABLE_SEED, authority.key().as_ref(), &table_id, &bump];
+    if create_program_address(&seed_slices, &crate::ID)? != *table.key() {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Rejects bad tree sizes before paying for the account
+    let space = shared_table_len(publish.tree_data.len())?;
+    let lamports = Rent::get()?.minimum_balance(space);
+    let signers = [Signer::from(&seeds)];
+    if table.lamports() == 0 {
+        CreateAccount {
+            from: authority,
+            to: table,
+            lamports,
+            space: space as u64,
+            owner: &crate::ID,
+        }
+        .invoke_signed(&signers)?;
+    } else {
+        // `CreateAccount` fails on funded accounts, and anyone can send lamports to
+        // the PDA to squat the table id, so top it up and claim it in steps
+        let top_up = lamports.saturating_sub(table.lamports());
+        if top_up > 0 {
+            Transfer {
+                from: authority,
+                to: table,
+                lamports: top_up,
+            }
+            .invoke()?;
+        }
+        Allocate {
+            account: table,
+            space: space as u64,
+        }
+        .invoke_signed(&signers)?;
+        Assign {
+            account: table,
+            owner: &crate::ID,
+        }
+        .invoke_signed(&signers)?;
+    }
+
+    let mut data = table.try_borrow_mut_data()?;
+    write_shared_table(publish.tree_data, publish.table_id, &mut data)?;
 
     Ok(())
 }
@@ -0,0 +1,112 @@
+use crate::instruction::{
+    decoder::{
+        decode_bits_prefix, try_build_tree, DecodeOptions, Node, TreeBuilder, MAX_TREE_DEPTH,
+        MAX_TREE_NODES, SMALL_TREE_DEPTH, SMALL_TREE_NODES,
+    },
+    format::{read_length, Header, FLAG_CHECKSUM, MODE_BATCH},
+    DecodeError, MAX_URL_LEN,
+};
+
+/// Decodes every URL of a [`MODE_BATCH`] payload in order, calling `f` with each
+/// one, and returns the number of URLs.
+///
+/// The tree is built once for the whole batch. Each URL is decoded into the same
+/// [`MAX_URL_LEN`] buffer, so `f` must copy out whatever it keeps. An error returned
+/// by `f` stops decoding and is returned as is.
+///
+/// Batches carry no checksum trailer: a format byte with [`FLAG_CHECKSUM`] fails
+/// with [`DecodeError::UnsupportedFormat`].
+#[inline(always)]
+pub fn huffman_decode_batch<F>(
+    instruction_data: &[u8],
+    options: DecodeOptions,
+    f: F,
+) -> Result<usize, DecodeError>
+where
+    F: FnMut(&[u8]) -> Result<(), DecodeError>,
+{
+    let (header, rest) = Header::parse(instruction_data)?;
+    if header.mode() != MODE_BATCH || header.format & FLAG_CHECKSUM != 0 {
+        return Err(DecodeError::UnsupportedFormat);
+    }
+    let tree_size = header.tree_size;
+    if tree_size > rest.len() {
+        return Err(DecodeError::TruncatedTree);
+    }
+    let (tree_data, records) = rest.split_at(tree_size);
+
+    if tree_size <= SMALL_TREE_NODES {
+        let mut builder = TreeBuilder::<SMALL_TREE_NODES, SMALL_TREE_DEPTH>::new();
+        let root_idx = try_build_tree(tree_data, &mut builder, false)?;
+        decode_records(&builder.nodes, root_idx, &header, records, options, f)
+    } else {
+        decode_batch_full_alphabet(&header, tree_data, records, options, f)
+    }
+}
+
+/// Full alphabet path, kept out of line so the 511 entry node table gets its own
+/// stack frame, apart from the caller and the output buffer of [`decode_records`].
+#[inline(never)]
+fn decode_batch_full_alphabet<F>(
+    header: &Header,
+    tree_data: &[u8],
+    records: &[u8],
+    options: DecodeOptions,
+    f: F,
+) -> Result<usize, DecodeError>
+where
+    F: FnMut(&[u8]) -> Result<(), DecodeError>,
+{
+    let mut builder = TreeBuilder::<MAX_TREE_NODES, MAX_TREE_DEPTH>::new();
+    let root_idx = try_build_tree(tree_data, &mut builder, false)?;
+    decode_records(&builder.nodes, root_idx, header, records, options, f)
+}
+
+/// Kept out of line so the output buffer gets its own stack frame, apart from the
+/// node table.
+#[inline(never)]
+fn decode_records<F>(
+    nodes: &[Node],
+    root_idx: u16,
+    header: &Header,
+    records: &[u8],
+    options: DecodeOptions,
+    mut f: F,
+) -> Result<usize, DecodeError>
+where
+    F: FnMut(&[u8]) -> Result<(), DecodeError>,
+{
+    let (count, mut records) = match records {
+        [count, rest @ ..] => (count, rest),
+        [] => return Err(DecodeError::TruncatedHeader),
+    };
+
+    let mut out = [0u8; MAX_URL_LEN];
+    let mut max_len = 0;
+    for _ in 0..*count {
+        let (original_len, rest) = read_length(records, header.is_wide())?;
+        max_len = max_len.max(original_len);
+        if original_len > out.len() {
+            return Err(DecodeError::OutputOverflow);
+        }
+
+        let url = &mut out[..original_len];
+        let used_bytes = decode_bits_prefix(nodes, root_idx, rest, url, options)?;
+        f(url)?;
+        records = &rest[used_bytes..];
+    }
+
+    if options.strict && !records.is_empty() {
+        return Err(DecodeError::TrailingData);
+    }
+    // The longest record sets the width, like `original_len` in other modes
+    let widest = Header {
+        original_len: max_len,
+        ..*header
+    };
+    if options.strict && widest.is_needlessly_wide() {
+        return Err(DecodeError::NeedlesslyWide);
+    }
+
+    Ok(*count as usize)
+}
@@ -0,0 +1,55 @@
+/// MSB-first bit reader over a byte slice.
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    current: u8,
+    remaining: u8,
+}
+
+impl<'a> BitReader<'a> {
+    #[inline(always)]
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            current: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Reads the next bit, or `None` once the data is exhausted.
+    #[inline(always)]
+    pub(crate) fn read_bit(&mut self) -> Option<u8> {
+        if self.remaining == 0 {
+            self.current = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.remaining = 8;
+        }
+        let bit = self.current >> 7;
+        self.current <<= 1;
+        self.remaining -= 1;
+        Some(bit)
+    }
+
+    /// Reads the next 8 bits as a byte, or `None` once the data is exhausted.
+    #[inline(always)]
+    pub(crate) fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | self.read_bit()?;
+        }
+        Some(byte)
+    }
+
+    /// Bytes touched so far, counting a partially read byte.
+    #[inline(always)]
+    pub(crate) fn bytes_consumed(&self) -> usize {
+        self.pos
+    }
+
+    /// Unread bits of the current byte, left aligned. Zero when they are all zero.
+    #[inline(always)]
+    pub(crate) fn padding(&self) -> u8 {
+        self.current
+    }
+}
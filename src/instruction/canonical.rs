@@ -0,0 +1,156 @@
+use crate::instruction::{
+    bits::BitReader,
+    decoder::{fill_single_symbol, DecodeOptions},
+    format::MAX_CODE_LEN,
+    static_table::{ESCAPE_SYMBOL, STATIC_SYMBOLS, STATIC_TABLE},
+    DecodeError,
+};
+
+/// Decodes a [`MODE_CANONICAL`](crate::instruction::format::MODE_CANONICAL) payload.
+///
+/// Codes are rebuilt from the per-length counts the same way the encoder assigns
+/// them: within a length, consecutive codes go to symbols in ascending order, and
+/// the first code of each length follows the last code of the previous one.
+#[inline(always)]
+pub(crate) fn decode_canonical(
+    table: &[u8],
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<(), DecodeError> {
+    let [max_len, rest @ ..] = table else {
+        return Err(DecodeError::MalformedTree);
+    };
+    let max_len = *max_len as usize;
+    if max_len == 0 || max_len > MAX_CODE_LEN {
+        return Err(DecodeError::InvalidCodeTable);
+    }
+    if rest.len() < max_len {
+        return Err(DecodeError::MalformedTree);
+    }
+    let (counts, symbols) = rest.split_at(max_len);
+
+    validate_table(counts, symbols)?;
+    decode_codes(counts, symbols, false, encoded_bits, out, options)
+}
+
+/// Decodes a [`MODE_STATIC`](crate::instruction::format::MODE_STATIC) payload with
+/// the built-in [`STATIC_TABLE`], which is known to be valid, expanding
+/// [`ESCAPE_SYMBOL`] codes into the literal byte that follows them.
+#[inline(always)]
+pub(crate) fn decode_static(
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<(), DecodeError> {
+    let max_len = STATIC_TABLE[0] as usize;
+    let (counts, symbols) = STATIC_TABLE[1..].split_at(max_len);
+    decode_codes(counts, symbols, true, encoded_bits, out, options)
+}
+
+#[inline(always)]
+fn decode_codes(
+    counts: &[u8],
+    symbols: &[u8],
+    escape: bool,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<(), DecodeError> {
+    if symbols.len() == 1 {
+        return fill_single_symbol(symbols[0], encoded_bits, out, options);
+    }
+
+    let mut reader = BitReader::new(encoded_bits);
+    for slot in out.iter_mut() {
+        let mut code = 0u32;
+        let mut first = 0u32;
+        let mut index = 0usize;
+
+        // The code is complete, so every bit sequence ends on a symbol within max_len
+        for &count in counts {
+            code |= reader.read_bit().ok_or(DecodeError::BitstreamExhausted)? as u32;
+
+            let count = count as u32;
+            let offset = code.wrapping_sub(first);
+            if offset < count {
+                *slot = symbols[index + offset as usize];
+                if escape && *slot == ESCAPE_SYMBOL {
+                    let byte = reader.read_byte().ok_or(DecodeError::BitstreamExhausted)?;
+                    if options.strict
+                        && STATIC_SYMBOLS[(byte >> 5) as usize] & (1 << (byte & 31)) != 0
+                    {
+                        return Err(DecodeError::RedundantEscape);
+                    }
+                    *slot = byte;
+                }
+                break;
+            }
+
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+    }
+
+    if options.strict {
+        if reader.padding() != 0 {
+            return Err(DecodeError::NonZeroPadding);
+        }
+        if reader.bytes_consumed() != encoded_bits.len() {
+            return Err(DecodeError::TrailingData);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the table describes exactly one complete prefix code.
+#[inline(always)]
+fn validate_table(counts: &[u8], symbols: &[u8]) -> Result<(), DecodeError> {
+    // The longest length must be used, otherwise `max_len` is not canonical
+    if counts.last() == Some(&0) {
+        return Err(DecodeError::InvalidCodeTable);
+    }
+
+    let mut total = 0usize;
+    let mut unused_codes = 1i32;
+    for &count in counts {
+        unused_codes = (unused_codes << 1) - count as i32;
+        if unused_codes < 0 {
+            return Err(DecodeError::InvalidCodeTable);
+        }
+        total += count as usize;
+    }
+    // Only a single symbol, sent as a one bit code, may leave codes unused
+    if unused_codes != 0 && !(total == 1 && counts.len() == 1) {
+        return Err(DecodeError::InvalidCodeTable);
+    }
+
+    if symbols.len() < total {
+        return Err(DecodeError::MalformedTree);
+    }
+    if symbols.len() > total {
+        return Err(DecodeError::TrailingTreeData);
+    }
+
+    let mut seen_symbols = [0u32; 8];
+    let mut start = 0;
+    for &count in counts {
+        let group = &symbols[start..start + count as usize];
+        for (i, &symbol) in group.iter().enumerate() {
+            let (word, mask) = ((symbol >> 5) as usize, 1u32 << (symbol & 31));
+            if seen_symbols[word] & mask != 0 {
+                return Err(DecodeError::DuplicateSymbol);
+            }
+            seen_symbols[word] |= mask;
+
+            if i > 0 && group[i - 1] > symbol {
+                return Err(DecodeError::InvalidCodeTable);
+            }
+        }
+        start += count as usize;
+    }
+
+    Ok(())
+}
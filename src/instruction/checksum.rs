@@ -0,0 +1,68 @@
+use crate::instruction::{format::FLAG_CHECKSUM, DecodeError};
+
+/// Size of the checksum trailer: the first bytes of the SHA-256 of the decoded URL.
+pub const CHECKSUM_LEN: usize = 4;
+
+/// Splits the checksum trailer off payloads with [`FLAG_CHECKSUM`], returning the
+/// remaining payload and the expected checksum.
+#[inline(always)]
+pub fn split_checksum(
+    instruction_data: &[u8],
+) -> Result<(&[u8], Option<[u8; CHECKSUM_LEN]>), DecodeError> {
+    let Some(&format) = instruction_data.first() else {
+        return Err(DecodeError::TruncatedHeader);
+    };
+    if format & FLAG_CHECKSUM == 0 {
+        return Ok((instruction_data, None));
+    }
+
+    // The trailer never overlaps the format byte
+    if instruction_data.len() < 1 + CHECKSUM_LEN {
+        return Err(DecodeError::TruncatedHeader);
+    }
+    let (payload, trailer) = instruction_data.split_at(instruction_data.len() - CHECKSUM_LEN);
+    Ok((
+        payload,
+        Some([trailer[0], trailer[1], trailer[2], trailer[3]]),
+    ))
+}
+
+/// Checks `url` against the checksum taken from the trailer, if any.
+#[inline(always)]
+pub fn verify_checksum(
+    url: &[u8],
+    expected: Option<[u8; CHECKSUM_LEN]>,
+) -> Result<(), DecodeError> {
+    match expected {
+        Some(expected) if url_checksum(url) != expected => Err(DecodeError::ChecksumMismatch),
+        _ => Ok(()),
+    }
+}
+
+/// Truncated SHA-256 of `url`, the value carried by the checksum trailer.
+#[inline(always)]
+pub fn url_checksum(url: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = sha256(url);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+#[cfg(target_os = "solana")]
+#[inline(always)]
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    let vals = [data];
+    // SAFETY: `vals` is a slice of one byte slice, the layout the syscall expects,
+    // and `hash` has room for the 32 byte digest. Hashing cannot fail.
+    unsafe {
+        pinocchio::syscalls::sol_sha256(vals.as_ptr() as *const u8, 1, hash.as_mut_ptr());
+    }
+    hash
+}
+
+/// SHA-256 for clients and host tests, which have no syscall.
+#[cfg(not(target_os = "solana"))]
+#[inline(always)]
+fn sha256(data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(data).into()
+}
@@ -1,17 +1,45 @@
+use crate::instruction::{
+    bits::BitReader,
+    canonical::{decode_canonical, decode_static},
+    checksum::{split_checksum, verify_checksum},
+    format::{
+        Header, FLAG_WIDE, MARKER_EOS, MARKER_LEAF, MODE_BATCH, MODE_CANONICAL, MODE_PACKED_TREE,
+        MODE_SHARED, MODE_STATIC, MODE_STORED,
+    },
+    utf8::validate_utf8,
+    DecodeError, MAX_URL_LEN,
+};
+
+/// Node table size needed for the full 256 symbol alphabet.
+pub const MAX_TREE_NODES: usize = 511;
+/// Deepest pre-order nesting the full alphabet can produce (a degenerate chain).
+pub(crate) const MAX_TREE_DEPTH: usize = 255;
+/// Node table used for trees of at most this many bytes, which covers typical URLs.
+pub(crate) const SMALL_TREE_NODES: usize = 64;
+pub(crate) const SMALL_TREE_DEPTH: usize = 32;
+/// Packed trees of at most this many bytes fit the small node table: `n` leaves
+/// take `10n - 1` bits, so 40 bytes hold at most 32 leaves and 63 nodes.
+const SMALL_PACKED_TREE_BYTES: usize = 40;
+
+/// A tree node. `leaf` is a `u8` rather than a `bool` (0 internal, 1 symbol, 2
+/// end-of-stream) so that any byte pattern is a valid node, which lets
+/// [`StreamingDecoder`] keep its node table in account data.
+///
+/// [`StreamingDecoder`]: crate::instruction::streaming::StreamingDecoder
 #[derive(Clone, Copy)]
 #[repr(C)]
-struct Node {
-    is_leaf: bool,
-    byte_value: u8,
-    left: u8,
-    right: u8,
+pub(crate) struct Node {
+    leaf: u8,
+    pub(crate) byte_value: u8,
+    pub(crate) left: u16,
+    pub(crate) right: u16,
 }
 
 impl Node {
     #[inline(always)]
     fn new_leaf(byte_value: u8) -> Self {
         Self {
-            is_leaf: true,
+            leaf: 1,
             byte_value,
             left: 0,
             right: 0,
@@ -19,36 +47,506 @@ impl Node {
     }
 
     #[inline(always)]
-    fn new_internal(left: u8, right: u8) -> Self {
+    fn new_eos() -> Self {
         Self {
-            is_leaf: false,
+            leaf: 2,
+            byte_value: 0,
+            left: 0,
+            right: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn new_internal(left: u16, right: u16) -> Self {
+        Self {
+            leaf: 0,
             byte_value: 0,
             left,
             right,
         }
     }
+
+    #[inline(always)]
+    pub(crate) fn is_leaf(&self) -> bool {
+        self.leaf != 0
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_eos(&self) -> bool {
+        self.leaf == 2
+    }
+}
+
+/// A decoded URL, stored inline so decoding does not need an allocator.
+///
+/// `N` is the output capacity; it defaults to [`MAX_URL_LEN`].
+#[derive(Clone, Copy)]
+pub struct DecodedUrl<const N: usize = MAX_URL_LEN> {
+    pub(crate) len: usize,
+    pub(crate) bytes: [u8; N],
+}
+
+impl<const N: usize> DecodedUrl<N> {
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns the URL as a `&str`, failing with [`DecodeError::InvalidUtf8`] when a
+    /// byte-level tree decoded to a broken multi-byte sequence.
+    #[inline(always)]
+    pub fn as_str(&self) -> Result<&str, DecodeError> {
+        validate_utf8(self.as_bytes())
+    }
+}
+
+/// Options for the checked decoders.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Require the compact header whenever the lengths fit it, and the bit stream
+    /// to end in the byte holding the last symbol's final bit, with zero padding.
+    ///
+    /// This pins down the bytes of a URL for a given mode and code only. The same
+    /// URL is still accepted in every mode, with or without an end-of-stream leaf,
+    /// and with any valid tree shape, so strict mode alone does not make one
+    /// encoding per URL.
+    pub strict: bool,
+}
+
+impl DecodeOptions {
+    /// Options used by the program entrypoint.
+    pub const STRICT: Self = Self { strict: true };
+}
+
+/// Decodes a Huffman encoded URL, validating every read against the input.
+///
+/// See [`Header`] for the supported layouts.
+#[inline(always)]
+pub fn try_huffman_decode_url(instruction_data: &[u8]) -> Result<DecodedUrl, DecodeError> {
+    try_huffman_decode_url_with_options(instruction_data, DecodeOptions::default())
+}
+
+/// Same as [`try_huffman_decode_url`], also rejecting non-zero padding and bytes
+/// after the bit stream.
+#[inline(always)]
+pub fn try_huffman_decode_url_strict(instruction_data: &[u8]) -> Result<DecodedUrl, DecodeError> {
+    try_huffman_decode_url_with_options(instruction_data, DecodeOptions::STRICT)
+}
+
+/// Same as [`try_huffman_decode_url`] with a caller chosen output capacity `N`.
+///
+/// Payloads whose `original_len` exceeds `N` fail with [`DecodeError::OutputOverflow`].
+#[inline(always)]
+pub fn try_huffman_decode_url_with_capacity<const N: usize>(
+    instruction_data: &[u8],
+) -> Result<DecodedUrl<N>, DecodeError> {
+    try_huffman_decode_url_with_options(instruction_data, DecodeOptions::default())
+}
+
+/// Checked decoder with a caller chosen output capacity `N` and [`DecodeOptions`].
+#[inline(always)]
+pub fn try_huffman_decode_url_with_options<const N: usize>(
+    instruction_data: &[u8],
+    options: DecodeOptions,
+) -> Result<DecodedUrl<N>, DecodeError> {
+    let mut result = DecodedUrl {
+        len: 0,
+        bytes: [0u8; N],
+    };
+    result.len =
+        huffman_decode_url_into_with_options(instruction_data, &mut result.bytes, options)?;
+
+    Ok(result)
+}
+
+/// Decodes straight into `out`, e.g. an account's data or a return-data buffer,
+/// returning the number of bytes written.
+///
+/// Only the first `original_len` bytes of `out` are written; payloads whose
+/// `original_len` exceeds `out.len()` fail with [`DecodeError::OutputOverflow`].
+#[inline(always)]
+pub fn huffman_decode_url_into(
+    instruction_data: &[u8],
+    out: &mut [u8],
+) -> Result<usize, DecodeError> {
+    huffman_decode_url_into_with_options(instruction_data, out, DecodeOptions::default())
+}
+
+/// Same as [`huffman_decode_url_into`] with explicit [`DecodeOptions`].
+///
+/// Payloads with a checksum trailer are verified once decoded, failing with
+/// [`DecodeError::ChecksumMismatch`].
+#[inline(always)]
+pub fn huffman_decode_url_into_with_options(
+    instruction_data: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let (payload, checksum) = split_checksum(instruction_data)?;
+    let decoded_len = decode_payload(payload, out, options)?;
+    verify_checksum(&out[..decoded_len], checksum)?;
+
+    Ok(decoded_len)
+}
+
+/// Returns the URL of a [`MODE_STORED`] payload in place, verified against the
+/// checksum trailer if there is one. Unlike the copying decoders, which also
+/// accept stored payloads, this costs nothing per byte.
+#[inline(always)]
+pub fn stored_url(instruction_data: &[u8]) -> Result<&[u8], DecodeError> {
+    let (payload, checksum) = split_checksum(instruction_data)?;
+    let (header, url) = Header::parse(payload)?;
+    if header.mode() != MODE_STORED {
+        return Err(DecodeError::UnsupportedFormat);
+    }
+    if url.len() > MAX_URL_LEN {
+        return Err(DecodeError::OutputOverflow);
+    }
+    verify_checksum(url, checksum)?;
+    Ok(url)
+}
+
+#[inline(always)]
+fn decode_payload(
+    payload: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let (header, rest) = Header::parse(payload)?;
+    if options.strict && header.is_needlessly_wide() {
+        return Err(DecodeError::NeedlesslyWide);
+    }
+    // Shared payloads need the table account, see `huffman_decode_url_into_with_table`,
+    // and batches have their own entry point, see `huffman_decode_batch`
+    if header.mode() == MODE_SHARED || header.mode() == MODE_BATCH {
+        return Err(DecodeError::UnsupportedFormat);
+    }
+    if header.mode() == MODE_STORED {
+        if rest.len() > out.len() {
+            return Err(DecodeError::OutputOverflow);
+        }
+        out[..rest.len()].copy_from_slice(rest);
+        return Ok(rest.len());
+    }
+    let original_len = header.original_len;
+    let tree_size = header.tree_size;
+
+    if !header.is_eos() && original_len > out.len() {
+        return Err(DecodeError::OutputOverflow);
+    }
+    if tree_size > rest.len() {
+        return Err(DecodeError::TruncatedTree);
+    }
+    let (tree_data, encoded_bits) = rest.split_at(tree_size);
+
+    // With an end-of-stream leaf the output length is only known once it is read
+    let out = if header.is_eos() {
+        out
+    } else {
+        &mut out[..original_len]
+    };
+    let small_tree = if header.mode() == MODE_PACKED_TREE {
+        tree_size <= SMALL_PACKED_TREE_BYTES
+    } else {
+        // Every node takes at least one tree byte
+        tree_size <= SMALL_TREE_NODES
+    };
+    if header.mode() == MODE_STATIC {
+        decode_static(encoded_bits, out, options)?;
+        Ok(original_len)
+    } else if header.mode() == MODE_CANONICAL {
+        decode_canonical(tree_data, encoded_bits, out, options)?;
+        Ok(original_len)
+    } else if small_tree {
+        decode_with_table::<SMALL_TREE_NODES, SMALL_TREE_DEPTH>(
+            &header,
+            tree_data,
+            encoded_bits,
+            out,
+            options,
+        )
+    } else {
+        decode_full_alphabet(&header, tree_data, encoded_bits, out, options)
+    }
+}
+
+/// Full alphabet path, kept out of line so the 511 entry node table does not
+/// share a stack frame with the caller's output buffer.
+#[inline(never)]
+fn decode_full_alphabet(
+    header: &Header,
+    tree_data: &[u8],
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    decode_with_table::<MAX_TREE_NODES, MAX_TREE_DEPTH>(
+        header,
+        tree_data,
+        encoded_bits,
+        out,
+        options,
+    )
+}
+
+/// Builds the tree of a [`MODE_TREE`] or [`MODE_PACKED_TREE`] payload and decodes
+/// its bits, returning the number of bytes written.
+///
+/// [`MODE_TREE`]: crate::instruction::format::MODE_TREE
+#[inline(always)]
+fn decode_with_table<const NODES: usize, const DEPTH: usize>(
+    header: &Header,
+    tree_data: &[u8],
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let mut builder = TreeBuilder::<NODES, DEPTH>::new();
+    let root_idx = if header.mode() == MODE_PACKED_TREE {
+        try_build_packed_tree(tree_data, &mut builder, options)?
+    } else {
+        try_build_tree(tree_data, &mut builder, header.is_eos())?
+    };
+
+    if header.is_eos() {
+        decode_bits_until_eos(&builder.nodes, root_idx, encoded_bits, out, options)
+    } else {
+        decode_bits(&builder.nodes, root_idx, encoded_bits, out, options)?;
+        Ok(out.len())
+    }
+}
+
+/// Walks the tree for every bit until `out` is full.
+#[inline(always)]
+pub(crate) fn decode_bits(
+    nodes: &[Node],
+    root_idx: u16,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<(), DecodeError> {
+    let used_bytes = decode_bits_prefix(nodes, root_idx, encoded_bits, out, options)?;
+    if options.strict && used_bytes != encoded_bits.len() {
+        return Err(DecodeError::TrailingData);
+    }
+
+    Ok(())
 }
 
+/// Same as [`decode_bits`] without the trailing data check, returning the number of
+/// bytes of `encoded_bits` used, so that another bit stream may follow.
 #[inline(always)]
-pub unsafe fn huffman_decode_url(instruction_data: &[u8]) -> (usize, [u8; 128]) {
-    let mut result = [0u8; 128];
+pub(crate) fn decode_bits_prefix(
+    nodes: &[Node],
+    root_idx: u16,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let original_len = out.len();
+    let root = nodes[root_idx as usize];
 
-    // Format: [original_len: 1][tree_size: 2][tree_data][encoded_bits]
-    let original_len = *instruction_data.get_unchecked(0) as usize;
+    if root.is_leaf() {
+        return fill_single_symbol_prefix(root.byte_value, encoded_bits, out, options);
+    }
+
+    let mut result_len = 0;
+    let mut current_node = root_idx;
+    let mut used_bytes = 0;
+    let mut padding = 0u8;
+    'bytes: for &byte in encoded_bits {
+        if result_len >= original_len {
+            break;
+        }
+        used_bytes += 1;
+
+        let mut bits = byte;
+        for _ in 0..8 {
+            let node = nodes[current_node as usize];
+            current_node = if bits & 0x80 == 0 {
+                node.left
+            } else {
+                node.right
+            };
+            bits <<= 1;
+
+            let next = nodes[current_node as usize];
+            if next.is_leaf() {
+                out[result_len] = next.byte_value;
+                result_len += 1;
+                current_node = root_idx;
 
-    // let tree_size = u16::from_le_bytes([
-    //     *instruction_data.get_unchecked(1),
-    //     *instruction_data.get_unchecked(2),
-    // ]) as usize;
-    let tree_size = *instruction_data.get_unchecked(1) as usize; // Assuming tree size is <= 255
+                if result_len >= original_len {
+                    // Unread bits of this byte are left in the high bits
+                    padding = bits;
+                    break 'bytes;
+                }
+            }
+        }
+    }
 
-    let data_start = 2 + tree_size;
+    if result_len < original_len {
+        return Err(DecodeError::BitstreamExhausted);
+    }
+    if options.strict && padding != 0 {
+        return Err(DecodeError::NonZeroPadding);
+    }
+
+    Ok(used_bytes)
+}
+
+/// Walks the tree for every bit until the end-of-stream leaf, returning the number
+/// of bytes written to `out`.
+#[inline(always)]
+fn decode_bits_until_eos(
+    nodes: &[Node],
+    root_idx: u16,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    // A lone end-of-stream leaf has no code to read
+    if nodes[root_idx as usize].is_leaf() {
+        return Err(DecodeError::MalformedTree);
+    }
+
+    let mut result_len = 0;
+    let mut current_node = root_idx;
+    let mut used_bytes = 0;
+    let mut padding = 0u8;
+    let mut ended = false;
+    'bytes: for &byte in encoded_bits {
+        used_bytes += 1;
+
+        let mut bits = byte;
+        for _ in 0..8 {
+            let node = nodes[current_node as usize];
+            current_node = if bits & 0x80 == 0 {
+                node.left
+            } else {
+                node.right
+            };
+            bits <<= 1;
+
+            let next = nodes[current_node as usize];
+            if next.is_leaf() {
+                if next.is_eos() {
+                    padding = bits;
+                    ended = true;
+                    break 'bytes;
+                }
+                if result_len == out.len() {
+                    return Err(DecodeError::OutputOverflow);
+                }
+                out[result_len] = next.byte_value;
+                result_len += 1;
+                current_node = root_idx;
+            }
+        }
+    }
+
+    if !ended {
+        return Err(DecodeError::BitstreamExhausted);
+    }
+    if options.strict {
+        if padding != 0 {
+            return Err(DecodeError::NonZeroPadding);
+        }
+        if used_bytes != encoded_bits.len() {
+            return Err(DecodeError::TrailingData);
+        }
+    }
+
+    Ok(result_len)
+}
+
+/// Decodes a code with a single symbol, sent as one zero bit per occurrence.
+#[inline(always)]
+pub(crate) fn fill_single_symbol(
+    symbol: u8,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<(), DecodeError> {
+    let used_bytes = fill_single_symbol_prefix(symbol, encoded_bits, out, options)?;
+    if options.strict && used_bytes != encoded_bits.len() {
+        return Err(DecodeError::TrailingData);
+    }
+
+    Ok(())
+}
+
+/// Same as [`fill_single_symbol`] without the trailing data check, returning the
+/// number of bytes of `encoded_bits` used.
+#[inline(always)]
+fn fill_single_symbol_prefix(
+    symbol: u8,
+    encoded_bits: &[u8],
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let used_bytes = out.len().div_ceil(8);
+    if encoded_bits.len() < used_bytes {
+        return Err(DecodeError::BitstreamExhausted);
+    }
+    if options.strict && encoded_bits[..used_bytes].iter().any(|&byte| byte != 0) {
+        return Err(DecodeError::NonZeroPadding);
+    }
+
+    out.fill(symbol);
+    Ok(used_bytes)
+}
+
+/// Decodes a Huffman encoded URL without any bounds checks.
+///
+/// This is the original challenge implementation and is only reachable from the
+/// entrypoint when the `unchecked-decode` feature is enabled, for CU benchmarking.
+///
+/// # Safety
+///
+/// `instruction_data` must be a well-formed payload produced by the encoder: the
+/// header must be present and use `MODE_TREE` with no flag other than `FLAG_WIDE`,
+/// `tree_size` must not exceed the remaining data, the tree must fit the 64 entry
+/// node table and the decoded URL must fit in the `N` byte output.
+#[inline(always)]
+pub unsafe fn huffman_decode_url<const N: usize>(instruction_data: &[u8]) -> (usize, [u8; N]) {
+    let mut result = [0u8; N];
+
+    // Format: [format: 1][original_len: 1|2][tree_size: 1|2][tree_data][encoded_bits]
+    let (original_len, tree_size, tree_start) =
+        if *instruction_data.get_unchecked(0) & FLAG_WIDE != 0 {
+            let original_len = u16::from_le_bytes([
+                *instruction_data.get_unchecked(1),
+                *instruction_data.get_unchecked(2),
+            ]) as usize;
+            let tree_size = u16::from_le_bytes([
+                *instruction_data.get_unchecked(3),
+                *instruction_data.get_unchecked(4),
+            ]) as usize;
+            (original_len, tree_size, 5)
+        } else {
+            let original_len = *instruction_data.get_unchecked(1) as usize;
+            let tree_size = *instruction_data.get_unchecked(2) as usize;
+            (original_len, tree_size, 3)
+        };
+
+    let data_start = tree_start + tree_size;
 
     // Build tree iteratively
     let mut nodes: [Node; 64] = [Node::new_leaf(0); 64];
-    let mut node_count = 0u8;
+    let mut node_count = 0u16;
     let root_idx = build_tree_iterative(
-        instruction_data.get_unchecked(2..2 + tree_size),
+        instruction_data.get_unchecked(tree_start..data_start),
         &mut nodes,
         &mut node_count,
     );
@@ -75,7 +573,7 @@ pub unsafe fn huffman_decode_url(instruction_data: &[u8]) -> (usize, [u8; 128])
 
             let node = *nodes.get_unchecked(current_node as usize);
 
-            if node.is_leaf {
+            if node.is_leaf() {
                 *result.get_unchecked_mut(result_len) = node.byte_value;
                 result_len += 1;
                 current_node = root_idx;
@@ -83,7 +581,7 @@ pub unsafe fn huffman_decode_url(instruction_data: &[u8]) -> (usize, [u8; 128])
                 // Inline root processing
                 if result_len < original_len {
                     let root_node = *nodes.get_unchecked(root_idx as usize);
-                    if !root_node.is_leaf {
+                    if !root_node.is_leaf() {
                         current_node = if bit == 0 {
                             root_node.left
                         } else {
@@ -101,9 +599,10 @@ pub unsafe fn huffman_decode_url(instruction_data: &[u8]) -> (usize, [u8; 128])
 }
 
 #[inline(always)]
-fn build_tree_iterative(tree_data: &[u8], nodes: &mut [Node; 64], node_count: &mut u8) -> u8 {
+fn build_tree_iterative(tree_data: &[u8], nodes: &mut [Node; 64], node_count: &mut u16) -> u16 {
     let mut pos = 0;
-    let mut stack: [u8; 16] = [0; 16];
+    // A 64 node tree has at most 31 internal nodes on one path
+    let mut stack: [u16; 32] = [0; 32];
     let mut _stack_top = 0;
 
     // Read first node
@@ -183,3 +682,247 @@ fn build_tree_iterative(tree_data: &[u8], nodes: &mut [Node; 64], node_count: &m
 
     root_idx
 }
+
+/// Bounds checked counterpart of [`build_tree_iterative`].
+///
+/// Besides the checks done by [`TreeBuilder`], the tree must be complete and end
+/// exactly at the end of `tree_data`. With `eos` it must have an end-of-stream leaf.
+#[inline(always)]
+pub(crate) fn try_build_tree<const NODES: usize, const DEPTH: usize>(
+    tree_data: &[u8],
+    builder: &mut TreeBuilder<NODES, DEPTH>,
+    eos: bool,
+) -> Result<u16, DecodeError> {
+    if eos {
+        builder.allow_eos();
+    }
+    for &byte in tree_data {
+        builder.push(byte)?;
+    }
+    if !builder.is_complete() || (eos && !builder.has_eos()) {
+        return Err(DecodeError::MalformedTree);
+    }
+
+    Ok(0)
+}
+
+/// Counterpart of [`try_build_tree`] for [`MODE_PACKED_TREE`], feeding the marker
+/// and symbol bits to the same [`TreeBuilder`].
+///
+/// The tree must end in the last byte of `tree_data`; in strict mode the padding
+/// bits after it must also be zero.
+#[inline(always)]
+fn try_build_packed_tree<const NODES: usize, const DEPTH: usize>(
+    tree_data: &[u8],
+    builder: &mut TreeBuilder<NODES, DEPTH>,
+    options: DecodeOptions,
+) -> Result<u16, DecodeError> {
+    let mut reader = BitReader::new(tree_data);
+    loop {
+        let marker = reader.read_bit().ok_or(DecodeError::MalformedTree)?;
+        let complete = if marker == 1 {
+            builder.push(1)?;
+            builder.push(reader.read_byte().ok_or(DecodeError::MalformedTree)?)?
+        } else {
+            builder.push(0)?
+        };
+        if complete {
+            break;
+        }
+    }
+
+    if reader.bytes_consumed() != tree_data.len() {
+        return Err(DecodeError::TrailingTreeData);
+    }
+    if options.strict && reader.padding() != 0 {
+        return Err(DecodeError::NonZeroPadding);
+    }
+
+    Ok(0)
+}
+
+/// Incremental pre-order tree parser, fed one serialized tree byte at a time.
+///
+/// `NODES` bounds the node table and `DEPTH` the stack of internal nodes still
+/// waiting for a child; exceeding either is reported instead of overwriting memory.
+///
+/// The tree is also validated structurally so that an accepted payload has exactly
+/// one decoding: markers must be 0 or 1 (or 2 for the single end-of-stream leaf,
+/// once [`TreeBuilder::allow_eos`] is called), every leaf symbol must be unique and
+/// no byte may follow the last node.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) struct TreeBuilder<const NODES: usize, const DEPTH: usize> {
+    pub(crate) nodes: [Node; NODES],
+    stack: [u16; DEPTH],
+    seen_symbols: [u32; 8],
+    node_count: u16,
+    stack_top: u16,
+    pending_leaf: u8,
+    complete: u8,
+    eos: u8,
+}
+
+const EOS_REJECTED: u8 = 0;
+const EOS_ALLOWED: u8 = 1;
+const EOS_SEEN: u8 = 2;
+
+impl<const NODES: usize, const DEPTH: usize> TreeBuilder<NODES, DEPTH> {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: [Node::new_leaf(0); NODES],
+            stack: [0; DEPTH],
+            seen_symbols: [0; 8],
+            node_count: 0,
+            stack_top: 0,
+            pending_leaf: 0,
+            complete: 0,
+            eos: EOS_REJECTED,
+        }
+    }
+
+    /// Starts a new tree. Nodes are overwritten as they are parsed, so the node
+    /// table itself does not need clearing.
+    #[inline(always)]
+    pub(crate) fn reset(&mut self) {
+        self.seen_symbols = [0; 8];
+        self.node_count = 0;
+        self.stack_top = 0;
+        self.pending_leaf = 0;
+        self.complete = 0;
+        self.eos = EOS_REJECTED;
+    }
+
+    /// Accepts one end-of-stream leaf (marker 2) in the tree being parsed.
+    #[inline(always)]
+    pub(crate) fn allow_eos(&mut self) {
+        self.eos = EOS_ALLOWED;
+    }
+
+    #[inline(always)]
+    pub(crate) fn has_eos(&self) -> bool {
+        self.eos == EOS_SEEN
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_complete(&self) -> bool {
+        self.complete != 0
+    }
+
+    #[inline(always)]
+    pub(crate) fn node_count(&self) -> usize {
+        self.node_count as usize
+    }
+
+    /// Whether every index `push` and the decoders follow is in range, for builders
+    /// loaded from untrusted bytes instead of built by `reset` and `push`.
+    pub(crate) fn is_consistent(&self) -> bool {
+        let node_count = self.node_count as usize;
+        let stack_top = self.stack_top as usize;
+        if node_count > NODES
+            || stack_top > DEPTH
+            || self.complete > 1
+            || self.eos > EOS_SEEN
+            || (self.pending_leaf != 0 && node_count == NODES)
+        {
+            return false;
+        }
+        // Every node after the root attaches to the stack top, until the stack
+        // empties and the tree is complete
+        let attachable = if self.complete != 0 {
+            node_count > 0 && stack_top == 0
+        } else {
+            node_count == 0 || stack_top > 0
+        };
+
+        attachable
+            && self.stack[..stack_top]
+                .iter()
+                .all(|&idx| (idx as usize) < node_count)
+            && self.nodes[..node_count].iter().all(|node| {
+                node.is_leaf()
+                    || ((node.left as usize) < node_count && (node.right as usize) < node_count)
+            })
+    }
+
+    /// Consumes one tree byte, returning `true` once the tree is complete.
+    #[inline(always)]
+    pub(crate) fn push(&mut self, byte: u8) -> Result<bool, DecodeError> {
+        if self.complete != 0 {
+            return Err(DecodeError::TrailingTreeData);
+        }
+
+        if self.pending_leaf != 0 {
+            self.pending_leaf = 0;
+
+            let (word, mask) = ((byte >> 5) as usize, 1u32 << (byte & 31));
+            if self.seen_symbols[word] & mask != 0 {
+                return Err(DecodeError::DuplicateSymbol);
+            }
+            self.seen_symbols[word] |= mask;
+
+            self.nodes[self.node_count as usize] = Node::new_leaf(byte);
+            return self.attach(false);
+        }
+
+        if byte > MARKER_EOS || (byte == MARKER_EOS && self.eos == EOS_REJECTED) {
+            return Err(DecodeError::InvalidNodeMarker);
+        }
+        if self.node_count as usize == NODES {
+            return Err(DecodeError::TreeTooLarge);
+        }
+
+        if byte == MARKER_EOS {
+            if self.eos == EOS_SEEN {
+                return Err(DecodeError::DuplicateSymbol);
+            }
+            self.eos = EOS_SEEN;
+            self.nodes[self.node_count as usize] = Node::new_eos();
+            return self.attach(false);
+        }
+
+        if byte == MARKER_LEAF {
+            // The symbol follows in the next byte
+            self.pending_leaf = 1;
+            return Ok(false);
+        }
+
+        self.nodes[self.node_count as usize] = Node::new_internal(0, 0);
+        self.attach(true)
+    }
+
+    /// Links the node just written at `node_count` to its parent and pushes it
+    /// on the stack when it still needs children.
+    #[inline(always)]
+    fn attach(&mut self, internal: bool) -> Result<bool, DecodeError> {
+        let current_idx = self.node_count;
+        self.node_count += 1;
+
+        if current_idx != 0 {
+            // Attach to parent
+            let parent_idx = self.stack[self.stack_top as usize - 1];
+            let parent = &mut self.nodes[parent_idx as usize];
+
+            if parent.left == 0 {
+                parent.left = current_idx;
+            } else {
+                parent.right = current_idx;
+                self.stack_top -= 1;
+            }
+        }
+
+        if internal {
+            if self.stack_top as usize == DEPTH {
+                return Err(DecodeError::StackOverflow);
+            }
+            self.stack[self.stack_top as usize] = current_idx;
+            self.stack_top += 1;
+        }
+
+        if self.stack_top == 0 {
+            self.complete = 1;
+        }
+        Ok(self.complete != 0)
+    }
+}
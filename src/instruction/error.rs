@@ -0,0 +1,69 @@
+use pinocchio::program_error::ProgramError;
+
+/// Errors returned while decoding a Huffman encoded URL.
+///
+/// Each variant maps to a stable `ProgramError::Custom` code so clients can
+/// tell failures apart from the transaction logs:
+///
+/// | Code | Variant              | Meaning                                                                    |
+/// |------|----------------------|----------------------------------------------------------------------------|
+/// | 0    | `TruncatedHeader`    | Instruction data is shorter than the header                                |
+/// | 1    | `TruncatedTree`      | `tree_size` points past the end of the data                                |
+/// | 2    | `TreeTooLarge`       | Tree has more nodes than the node table can hold                           |
+/// | 3    | `MalformedTree`      | Tree or code table is cut short or incomplete, see below                   |
+/// | 4    | `StackOverflow`      | Tree is deeper than the parser stack can hold                              |
+/// | 5    | `BitstreamExhausted` | Bit stream ended before `original_len` bytes                               |
+/// | 6    | `OutputOverflow`     | `original_len` is larger than the output buffer                            |
+/// | 7    | `InvalidUtf8`        | Decoded bytes are not valid UTF-8                                          |
+/// | 8    | `UnsupportedFormat`  | Unknown mode or flag bits in the format byte                               |
+/// | 9    | `InvalidNodeMarker`  | Node marker is not 0 (internal), 1 (leaf), or 2 (EOS leaf) with `FLAG_EOS` |
+/// | 10   | `TrailingTreeData`   | Tree region has bytes left after the complete tree                         |
+/// | 11   | `DuplicateSymbol`    | The same byte appears in more than one leaf                                |
+/// | 12   | `NonZeroPadding`     | Padding bits after the last symbol are not zero                            |
+/// | 13   | `TrailingData`       | Bytes follow the byte holding the last symbol                              |
+/// | 14   | `InvalidCodeTable`   | Canonical table is incomplete, unsorted or too long                        |
+/// | 15   | `InvalidSharedTable` | Table account does not hold a published table                              |
+/// | 16   | `ChecksumMismatch`   | Decoded URL does not match the checksum trailer                            |
+/// | 17   | `RedundantEscape`    | Escaped literal has its own code in the static table                       |
+/// | 18   | `NeedlesslyWide`     | Wide header whose lengths fit the compact layout                           |
+///
+/// `MalformedTree` covers every way a code description can end too early or be
+/// incomplete: a pre-order tree that stops before its last leaf, a [`FLAG_EOS`]
+/// tree without its end-of-stream leaf or made of that leaf alone, a canonical
+/// table shorter than its counts say, and a shared table whose tree size cannot
+/// describe a complete tree (see [`shared_table_len`]).
+///
+/// [`FLAG_EOS`]: crate::instruction::format::FLAG_EOS
+/// [`shared_table_len`]: crate::instruction::shared_table::shared_table_len
+///
+/// New variants are only ever appended, existing codes never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DecodeError {
+    TruncatedHeader = 0,
+    TruncatedTree = 1,
+    TreeTooLarge = 2,
+    MalformedTree = 3,
+    StackOverflow = 4,
+    BitstreamExhausted = 5,
+    OutputOverflow = 6,
+    InvalidUtf8 = 7,
+    UnsupportedFormat = 8,
+    InvalidNodeMarker = 9,
+    TrailingTreeData = 10,
+    DuplicateSymbol = 11,
+    NonZeroPadding = 12,
+    TrailingData = 13,
+    InvalidCodeTable = 14,
+    InvalidSharedTable = 15,
+    ChecksumMismatch = 16,
+    RedundantEscape = 17,
+    NeedlesslyWide = 18,
+}
+
+impl From<DecodeError> for ProgramError {
+    #[inline(always)]
+    fn from(error: DecodeError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
@@ -0,0 +1,261 @@
+use crate::instruction::DecodeError;
+
+/// Mask selecting the encoding mode from the format byte.
+pub const MODE_MASK: u8 = 0x0f;
+/// Pre-order serialized Huffman tree followed by the bit stream.
+pub const MODE_TREE: u8 = 0;
+/// Canonical Huffman code lengths followed by the bit stream.
+///
+/// The table is `[max_len: 1][count of codes per length 1..=max_len][symbols]`,
+/// with symbols sorted by code length and then by byte value.
+pub const MODE_CANONICAL: u8 = 1;
+/// Bit stream coded with the program's built-in
+/// [`STATIC_TABLE`](crate::instruction::static_table::STATIC_TABLE), no tree is sent.
+///
+/// Layout: `[format: 1][original_len: 1|2][encoded_bits]`
+pub const MODE_STATIC: u8 = 2;
+/// Bit stream coded with a table published on chain, see
+/// [`shared_table`](crate::instruction::shared_table). The table account is passed
+/// as the instruction's only account.
+///
+/// Layout: `[format: 1][original_len: 1|2][encoded_bits]`
+pub const MODE_SHARED: u8 = 3;
+/// Pre-order tree packed at the bit level, followed by the bit stream.
+///
+/// Each node is a marker bit, 0 for internal and 1 for a leaf, and every leaf
+/// marker is followed by the 8 bits of its symbol, MSB first. The tree is padded
+/// with zero bits to a whole number of bytes and `tree_size` counts those bytes.
+pub const MODE_PACKED_TREE: u8 = 4;
+/// Several URLs sharing one pre-order serialized tree, see
+/// [`huffman_decode_batch`](crate::instruction::batch::huffman_decode_batch).
+///
+/// Layout: `[format: 1][tree_size: 1|2][tree_data][count: 1]` followed by `count`
+/// records `[original_len: 1|2][encoded_bits]`. Each bit stream ends with the byte
+/// holding its last symbol. [`Header::with_mode`] picks the field width from the
+/// longest URL. Batches take no [`FLAG_CHECKSUM`].
+pub const MODE_BATCH: u8 = 5;
+/// The URL bytes as they are, for URLs Huffman coding would make larger.
+///
+/// Layout: `[format: 1][url]`, the URL runs to the end of the payload. Having no
+/// length fields, it takes no [`FLAG_WIDE`].
+pub const MODE_STORED: u8 = 6;
+/// Not a payload encoding: marks the instruction that publishes a shared table, see
+/// [`PublishTable`](crate::instruction::shared_table::PublishTable).
+pub const MODE_PUBLISH_TABLE: u8 = MODE_MASK;
+
+/// Pre-order tree serialization: marker of an internal node, followed by its left
+/// and right subtrees.
+pub const MARKER_INTERNAL: u8 = 0;
+/// Marker of a leaf, followed by its symbol byte.
+pub const MARKER_LEAF: u8 = 1;
+/// Marker of the end-of-stream leaf of [`FLAG_EOS`] trees, without a symbol.
+pub const MARKER_EOS: u8 = 2;
+
+/// Longest canonical code the decoder accepts. A Huffman code of length `n`
+/// needs a total weight of at least Fibonacci(n + 2), so no URL of up to
+/// `MAX_URL_LEN` bytes needs more than 15 bits.
+pub const MAX_CODE_LEN: usize = 16;
+
+/// `original_len` and `tree_size` are little endian `u16` instead of `u8`.
+pub const FLAG_WIDE: u8 = 0x10;
+/// [`MODE_TREE`] only: there is no `original_len` field, instead the tree has one
+/// end-of-stream leaf, serialized as marker byte `2` without a symbol, and
+/// decoding stops once its code is read.
+pub const FLAG_EOS: u8 = 0x20;
+/// The last [`CHECKSUM_LEN`] bytes of the instruction data are a truncated SHA-256
+/// of the decoded URL, checked after decoding. Header and payload are unchanged.
+///
+/// [`CHECKSUM_LEN`]: crate::instruction::checksum::CHECKSUM_LEN
+pub const FLAG_CHECKSUM: u8 = 0x40;
+/// Every flag bit this program understands.
+pub const KNOWN_FLAGS: u8 = FLAG_WIDE | FLAG_EOS | FLAG_CHECKSUM;
+
+/// Size of the wide header, the largest layout.
+pub const MAX_HEADER_LEN: usize = 5;
+
+/// Instruction header, shared by the on-chain decoder and the client encoder.
+///
+/// Compact: `[format: 1][original_len: 1][tree_size: 1][tree_data][encoded_bits]`
+/// Wide:    `[format: 1][original_len: 2][tree_size: 2][tree_data][encoded_bits]`
+///
+/// `tree_size` is the size of whatever describes the code for the mode, the
+/// serialized tree, the packed tree or the canonical code table. [`MODE_STATIC`]
+/// and [`MODE_SHARED`] have no `tree_size` field, [`FLAG_EOS`] and [`MODE_BATCH`]
+/// payloads no `original_len` field (it is parsed as 0), and [`MODE_STORED`]
+/// payloads neither.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub format: u8,
+    pub original_len: usize,
+    pub tree_size: usize,
+}
+
+impl Header {
+    /// Header for [`MODE_TREE`], see [`Header::with_mode`].
+    #[inline(always)]
+    pub fn new(original_len: usize, tree_size: usize) -> Self {
+        Self::with_mode(MODE_TREE, original_len, tree_size)
+    }
+
+    /// Picks the compact layout whenever both lengths fit in a byte.
+    #[inline(always)]
+    pub fn with_mode(mode: u8, original_len: usize, tree_size: usize) -> Self {
+        let format = if original_len > u8::MAX as usize || tree_size > u8::MAX as usize {
+            mode | FLAG_WIDE
+        } else {
+            mode
+        };
+        Self {
+            format,
+            original_len,
+            tree_size,
+        }
+    }
+
+    /// Header for a [`MODE_TREE`] payload terminated by an end-of-stream leaf.
+    #[inline(always)]
+    pub fn with_eos(tree_size: usize) -> Self {
+        let mut header = Self::new(0, tree_size);
+        header.format |= FLAG_EOS;
+        header
+    }
+
+    #[inline(always)]
+    pub fn mode(&self) -> u8 {
+        self.format & MODE_MASK
+    }
+
+    #[inline(always)]
+    pub fn is_wide(&self) -> bool {
+        self.format & FLAG_WIDE != 0
+    }
+
+    #[inline(always)]
+    pub fn is_eos(&self) -> bool {
+        self.format & FLAG_EOS != 0
+    }
+
+    /// Whether the header uses the wide layout although every length fits the
+    /// compact one, which [`Header::with_mode`] never does. Strict decoding rejects
+    /// such headers, see [`DecodeOptions::strict`].
+    ///
+    /// [`DecodeOptions::strict`]: crate::instruction::decoder::DecodeOptions::strict
+    #[inline(always)]
+    pub fn is_needlessly_wide(&self) -> bool {
+        self.is_wide()
+            && self.original_len <= u8::MAX as usize
+            && self.tree_size <= u8::MAX as usize
+    }
+
+    /// Size of the encoded header in bytes.
+    #[inline(always)]
+    pub fn encoded_len(&self) -> usize {
+        Self::encoded_len_for(self.format)
+    }
+
+    /// Size of the header introduced by `format`, known once its first byte is read.
+    #[inline(always)]
+    pub fn encoded_len_for(format: u8) -> usize {
+        let field_len = if format & FLAG_WIDE != 0 { 2 } else { 1 };
+        let fields = Self::has_original_len(format) as usize + Self::has_tree_size(format) as usize;
+        1 + fields * field_len
+    }
+
+    /// Whether `format` carries an `original_len` field, absent with [`FLAG_EOS`],
+    /// in [`MODE_BATCH`], whose records carry their own, and in [`MODE_STORED`].
+    #[inline(always)]
+    pub fn has_original_len(format: u8) -> bool {
+        format & FLAG_EOS == 0 && !matches!(format & MODE_MASK, MODE_BATCH | MODE_STORED)
+    }
+
+    /// Whether the mode of `format` carries a `tree_size` field. Modes with a code
+    /// known to the program omit it.
+    #[inline(always)]
+    pub fn has_tree_size(format: u8) -> bool {
+        !matches!(format & MODE_MASK, MODE_STATIC | MODE_SHARED | MODE_STORED)
+    }
+
+    /// Parses the header, returning it together with the bytes that follow it.
+    #[inline(always)]
+    pub fn parse(instruction_data: &[u8]) -> Result<(Self, &[u8]), DecodeError> {
+        let [format, rest @ ..] = instruction_data else {
+            return Err(DecodeError::TruncatedHeader);
+        };
+        let format = *format;
+        if format & MODE_MASK > MODE_STORED
+            || format & !MODE_MASK & !KNOWN_FLAGS != 0
+            || (format & FLAG_EOS != 0 && format & MODE_MASK != MODE_TREE)
+            || (format & FLAG_WIDE != 0 && format & MODE_MASK == MODE_STORED)
+        {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+
+        let wide = format & FLAG_WIDE != 0;
+        let (original_len, rest) = if Self::has_original_len(format) {
+            read_length(rest, wide)?
+        } else {
+            (0, rest)
+        };
+        let (tree_size, rest) = if Self::has_tree_size(format) {
+            read_length(rest, wide)?
+        } else {
+            (0, rest)
+        };
+
+        Ok((
+            Self {
+                format,
+                original_len,
+                tree_size,
+            },
+            rest,
+        ))
+    }
+
+    /// Writes the header into the start of `out`, returning the number of bytes written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than [`Header::encoded_len`], or if a length does
+    /// not fit its field, a byte or with [`FLAG_WIDE`] a `u16`.
+    #[inline(always)]
+    pub fn write(&self, out: &mut [u8]) -> usize {
+        out[0] = self.format;
+        let wide = self.is_wide();
+        let mut pos = 1;
+        if Self::has_original_len(self.format) {
+            pos += write_length(&mut out[pos..], self.original_len, wide);
+        }
+        if Self::has_tree_size(self.format) {
+            pos += write_length(&mut out[pos..], self.tree_size, wide);
+        }
+        pos
+    }
+}
+
+#[inline(always)]
+pub(crate) fn read_length(data: &[u8], wide: bool) -> Result<(usize, &[u8]), DecodeError> {
+    if wide {
+        let [lo, hi, rest @ ..] = data else {
+            return Err(DecodeError::TruncatedHeader);
+        };
+        Ok((u16::from_le_bytes([*lo, *hi]) as usize, rest))
+    } else {
+        let [len, rest @ ..] = data else {
+            return Err(DecodeError::TruncatedHeader);
+        };
+        Ok((*len as usize, rest))
+    }
+}
+
+#[inline(always)]
+pub(crate) fn write_length(out: &mut [u8], len: usize, wide: bool) -> usize {
+    if wide {
+        let len = u16::try_from(len).expect("length does not fit a wide header field");
+        out[..2].copy_from_slice(&len.to_le_bytes());
+        2
+    } else {
+        out[0] = u8::try_from(len).expect("length does not fit a compact header field");
+        1
+    }
+}
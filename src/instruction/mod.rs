@@ -1 +1,19 @@
+pub mod batch;
+mod bits;
+mod canonical;
+pub mod checksum;
 pub mod decoder;
+pub mod error;
+pub mod format;
+pub mod shared_table;
+pub mod static_table;
+pub mod streaming;
+pub mod utf8;
+
+pub use error::DecodeError;
+
+/// Largest decoded URL the program accepts, in bytes.
+///
+/// Matches the transaction packet size, so any URL that can be sent uncompressed
+/// can also be described by the `original_len` header field.
+pub const MAX_URL_LEN: usize = 1232;
@@ -0,0 +1,207 @@
+use core::mem::{align_of, size_of};
+
+use crate::instruction::{
+    checksum::{split_checksum, verify_checksum},
+    decoder::{
+        decode_bits, DecodeOptions, DecodedUrl, Node, TreeBuilder, MAX_TREE_DEPTH, MAX_TREE_NODES,
+    },
+    format::{Header, MODE_PUBLISH_TABLE, MODE_SHARED},
+    DecodeError, MAX_URL_LEN,
+};
+
+/// First seed of every table PDA: `[TABLE_SEED, authority, table_id (u32 LE), bump]`.
+pub const TABLE_SEED: &[u8] = b"table";
+
+/// Tag in the first byte of a published table account.
+pub const SHARED_TABLE_TAG: u8 = 1;
+
+/// Bytes before the node table: `[tag: 1][reserved: 3][table_id: 4]`. Being a
+/// multiple of 8 keeps the nodes aligned in account data.
+pub const SHARED_TABLE_HEADER_LEN: usize = 8;
+
+/// Instruction data of the publish instruction.
+///
+/// Layout: `[format = MODE_PUBLISH_TABLE][table_id: 4][bump: 1][tree_data]`, where
+/// `tree_data` is a pre-order serialized tree as sent in [`MODE_TREE`] payloads.
+///
+/// Accounts: `[signer, writable]` authority paying for the table, `[writable]` the
+/// table PDA, and the system program.
+///
+/// [`MODE_TREE`]: crate::instruction::format::MODE_TREE
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublishTable<'a> {
+    pub table_id: u32,
+    pub bump: u8,
+    pub tree_data: &'a [u8],
+}
+
+impl<'a> PublishTable<'a> {
+    #[inline(always)]
+    pub fn parse(instruction_data: &'a [u8]) -> Result<Self, DecodeError> {
+        let [format, id0, id1, id2, id3, bump, tree_data @ ..] = instruction_data else {
+            return Err(DecodeError::TruncatedHeader);
+        };
+        if *format != MODE_PUBLISH_TABLE {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+
+        Ok(Self {
+            table_id: u32::from_le_bytes([*id0, *id1, *id2, *id3]),
+            bump: *bump,
+            tree_data,
+        })
+    }
+}
+
+/// Account data size of a table published from `tree_size` serialized tree bytes.
+///
+/// A tree with `n` leaves takes `3n - 1` bytes (a marker per node and a symbol per
+/// leaf) and has `2n - 1` nodes, so the size is known before the tree is parsed.
+#[inline(always)]
+pub fn shared_table_len(tree_size: usize) -> Result<usize, DecodeError> {
+    if tree_size % 3 != 2 {
+        return Err(DecodeError::MalformedTree);
+    }
+    let node_count = (2 * tree_size - 1) / 3;
+    if node_count > MAX_TREE_NODES {
+        return Err(DecodeError::TreeTooLarge);
+    }
+    Ok(SHARED_TABLE_HEADER_LEN + node_count * size_of::<Node>())
+}
+
+/// Validates `tree_data` and writes the published table into `data`, which must be
+/// exactly [`shared_table_len`] bytes long.
+///
+/// Kept out of line so the full-alphabet builder does not share a stack frame with
+/// the account handling of the caller.
+#[inline(never)]
+pub fn write_shared_table(
+    tree_data: &[u8],
+    table_id: u32,
+    data: &mut [u8],
+) -> Result<(), DecodeError> {
+    if shared_table_len(tree_data.len())? != data.len() {
+        return Err(DecodeError::InvalidSharedTable);
+    }
+
+    let mut builder = TreeBuilder::<MAX_TREE_NODES, MAX_TREE_DEPTH>::new();
+    for &byte in tree_data {
+        builder.push(byte)?;
+    }
+    if !builder.is_complete() {
+        return Err(DecodeError::MalformedTree);
+    }
+
+    let (header, nodes) = data.split_at_mut(SHARED_TABLE_HEADER_LEN);
+    header.copy_from_slice(&[0; SHARED_TABLE_HEADER_LEN]);
+    header[0] = SHARED_TABLE_TAG;
+    header[4..].copy_from_slice(&table_id.to_le_bytes());
+
+    let node_count = builder.node_count();
+    nodes_mut(nodes, node_count)?.copy_from_slice(&builder.nodes[..node_count]);
+    Ok(())
+}
+
+/// A published node table, read in place from account data.
+#[derive(Clone, Copy)]
+pub struct SharedTable<'a> {
+    table_id: u32,
+    nodes: &'a [Node],
+}
+
+impl<'a> SharedTable<'a> {
+    /// Reinterprets account data written by [`write_shared_table`], without copying it.
+    ///
+    /// Only the layout is checked; the caller must make sure the account is owned by
+    /// this program, so that the node links were produced by the tree builder.
+    #[inline(always)]
+    pub fn from_account_data(data: &'a [u8]) -> Result<Self, DecodeError> {
+        if data.len() < SHARED_TABLE_HEADER_LEN || data[0] != SHARED_TABLE_TAG {
+            return Err(DecodeError::InvalidSharedTable);
+        }
+        let (header, nodes) = data.split_at(SHARED_TABLE_HEADER_LEN);
+        let node_count = nodes.len() / size_of::<Node>();
+        if node_count == 0
+            || nodes.len() % size_of::<Node>() != 0
+            || nodes.as_ptr().align_offset(align_of::<Node>()) != 0
+        {
+            return Err(DecodeError::InvalidSharedTable);
+        }
+
+        Ok(Self {
+            table_id: u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+            // SAFETY: length and alignment are checked above and `Node` is made of
+            // integers only, so any byte pattern is a valid node.
+            nodes: unsafe {
+                core::slice::from_raw_parts(nodes.as_ptr() as *const Node, node_count)
+            },
+        })
+    }
+
+    #[inline(always)]
+    pub fn table_id(&self) -> u32 {
+        self.table_id
+    }
+}
+
+/// Decodes a [`MODE_SHARED`] payload with a table loaded from its account.
+#[inline(always)]
+pub fn try_huffman_decode_url_with_table(
+    instruction_data: &[u8],
+    table: &SharedTable,
+    options: DecodeOptions,
+) -> Result<DecodedUrl, DecodeError> {
+    let mut result = DecodedUrl {
+        len: 0,
+        bytes: [0u8; MAX_URL_LEN],
+    };
+    result.len =
+        huffman_decode_url_into_with_table(instruction_data, table, &mut result.bytes, options)?;
+
+    Ok(result)
+}
+
+/// Same as [`try_huffman_decode_url_with_table`], writing into `out` and returning
+/// the number of bytes written to `out`.
+#[inline(always)]
+pub fn huffman_decode_url_into_with_table(
+    instruction_data: &[u8],
+    table: &SharedTable,
+    out: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let (payload, checksum) = split_checksum(instruction_data)?;
+    let (header, encoded_bits) = Header::parse(payload)?;
+    if header.mode() != MODE_SHARED {
+        return Err(DecodeError::UnsupportedFormat);
+    }
+    if options.strict && header.is_needlessly_wide() {
+        return Err(DecodeError::NeedlesslyWide);
+    }
+    let original_len = header.original_len;
+    if original_len > out.len() {
+        return Err(DecodeError::OutputOverflow);
+    }
+
+    decode_bits(
+        table.nodes,
+        0,
+        encoded_bits,
+        &mut out[..original_len],
+        options,
+    )?;
+    verify_checksum(&out[..original_len], checksum)?;
+    Ok(original_len)
+}
+
+#[inline(always)]
+fn nodes_mut(data: &mut [u8], node_count: usize) -> Result<&mut [Node], DecodeError> {
+    if data.len() != node_count * size_of::<Node>()
+        || data.as_ptr().align_offset(align_of::<Node>()) != 0
+    {
+        return Err(DecodeError::InvalidSharedTable);
+    }
+    // SAFETY: length and alignment are checked above and `Node` is made of integers
+    // only, so any byte pattern is a valid node.
+    Ok(unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut Node, node_count) })
+}
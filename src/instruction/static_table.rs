@@ -0,0 +1,64 @@
+/// Symbol of the static table that is not a byte of the URL: its code is followed
+/// by 8 raw bits holding the byte, so bytes missing from the table (non-ASCII
+/// UTF-8, control characters) can still be sent.
+pub const ESCAPE_SYMBOL: u8 = 0;
+
+/// Code table for [`MODE_STATIC`](crate::instruction::format::MODE_STATIC).
+///
+/// Same layout as a `MODE_CANONICAL` table: `[max_len][count of codes per
+/// length][symbols sorted by length, then byte value]`. The lengths are assigned
+/// by hand, not generated from a corpus: the bytes URLs are mostly made of
+/// (lowercase letters, `/`, `.`, `:`, digits) get the shortest codes, and every
+/// visible ASCII byte (`!` to `~`) has a code so it needs no escape. Space is not
+/// in the table: URLs percent-encode it. Changing a length must keep the code
+/// complete, which `test_static_table_is_a_valid_canonical_table` checks.
+///
+/// Bytes outside the table, including space and `0x00` itself, are sent as
+/// [`ESCAPE_SYMBOL`] followed by the byte as an 8-bit literal.
+///
+/// The client encoder derives its codes from this same constant.
+#[rustfmt::skip]
+pub const STATIC_TABLE: [u8; 108] = [
+    12,
+    // Codes per length, 1..=12
+    0, 0, 0, 6, 9, 9, 12, 17, 17, 4, 3, 18,
+    // 4 bits
+    b'/', b'a', b'e', b'o', b's', b't',
+    // 5 bits
+    b'.', b'c', b'h', b'i', b'l', b'm', b'n', b'p', b'r',
+    // 6 bits
+    b'-', b'0', b'2', b':', b'b', b'd', b'g', b'u', b'w',
+    // 7 bits
+    b'1', b'3', b'4', b'5', b'7', b'8', b'=', b'f', b'k', b'v', b'y', b'z',
+    // 8 bits
+    b'&', b'6', b'9', b'?', b'A', b'B', b'C', b'D', b'E', b'R', b'S', b'W',
+    b'X', b'_', b'j', b'q', b'x',
+    // 9 bits
+    b'%', b'@', b'F', b'G', b'H', b'J', b'K', b'L', b'M', b'N', b'P', b'Q',
+    b'T', b'U', b'V', b'Y', b'Z',
+    // 10 bits
+    b'#', b'+', b'I', b'O',
+    // 11 bits
+    b'|', b'}', b'~',
+    // 12 bits
+    ESCAPE_SYMBOL, b'!', b'"', b'$', b'\'', b'(', b')', b'*', b',', b';', b'<', b'>',
+    b'[', b'\\', b']', b'^', b'`', b'{',
+];
+
+/// Bytes with their own code in [`STATIC_TABLE`], as a 256-bit set. Strict
+/// decoding rejects escaped literals of these bytes, so every URL has a single
+/// static encoding. [`ESCAPE_SYMBOL`] is left out, it can only be sent escaped.
+pub const STATIC_SYMBOLS: [u32; 8] = static_symbols();
+
+const fn static_symbols() -> [u32; 8] {
+    let mut set = [0u32; 8];
+    let mut i = 1 + STATIC_TABLE[0] as usize;
+    while i < STATIC_TABLE.len() {
+        let symbol = STATIC_TABLE[i];
+        if symbol != ESCAPE_SYMBOL {
+            set[(symbol >> 5) as usize] |= 1 << (symbol & 31);
+        }
+        i += 1;
+    }
+    set
+}
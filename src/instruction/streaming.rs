@@ -0,0 +1,261 @@
+use core::mem::{align_of, size_of};
+
+use pinocchio::program_error::ProgramError;
+
+use crate::instruction::{
+    decoder::{DecodeOptions, TreeBuilder, MAX_TREE_DEPTH, MAX_TREE_NODES},
+    format::{Header, FLAG_CHECKSUM, MAX_HEADER_LEN, MODE_TREE},
+    DecodeError,
+};
+
+const PHASE_HEADER: u8 = 0;
+const PHASE_TREE: u8 = 1;
+const PHASE_BITS: u8 = 2;
+const PHASE_DONE: u8 = 3;
+
+/// Resumable decoder for payloads uploaded across several transactions.
+///
+/// It is fed consecutive chunks of the same `MODE_TREE` bytes
+/// [`huffman_decode_url_into`] takes, without `FLAG_EOS` or `FLAG_CHECKSUM`, and
+/// keeps the built node table, the current node and the output position between
+/// calls. The output buffer is owned by the caller and must be the same on every
+/// call.
+///
+/// The struct is `repr(C)` and made only of integers, so it can live directly in
+/// account data (see [`StreamingDecoder::from_account_data`]). All-zero data is a
+/// valid fresh, lenient decoder.
+///
+/// [`huffman_decode_url_into`]: crate::instruction::decoder::huffman_decode_url_into
+#[repr(C)]
+pub struct StreamingDecoder {
+    tree: TreeBuilder<MAX_TREE_NODES, MAX_TREE_DEPTH>,
+    header: [u8; MAX_HEADER_LEN],
+    header_len: u8,
+    phase: u8,
+    strict: u8,
+    original_len: u16,
+    tree_remaining: u16,
+    current_node: u16,
+    output_len: u16,
+}
+
+impl StreamingDecoder {
+    /// Account data size needed to store a decoder.
+    pub const LEN: usize = size_of::<Self>();
+
+    /// Creates a decoder on the stack. On chain prefer
+    /// [`StreamingDecoder::from_account_data`], the decoder is several KB large.
+    pub fn new(options: DecodeOptions) -> Self {
+        let mut decoder = Self {
+            tree: TreeBuilder::new(),
+            header: [0; MAX_HEADER_LEN],
+            header_len: 0,
+            phase: PHASE_HEADER,
+            strict: 0,
+            original_len: 0,
+            tree_remaining: 0,
+            current_node: 0,
+            output_len: 0,
+        };
+        decoder.reset(options);
+        decoder
+    }
+
+    /// Reinterprets account data as a decoder, without copying it.
+    ///
+    /// Data that [`StreamingDecoder::reset`] and [`StreamingDecoder::feed`] could
+    /// not have left behind, such as an unknown phase or an index past the node
+    /// table, fails with [`ProgramError::InvalidAccountData`].
+    #[inline(always)]
+    pub fn from_account_data(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        if data.len() < Self::LEN || data.as_ptr().align_offset(align_of::<Self>()) != 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // SAFETY: length and alignment are checked above and every field is an
+        // integer, so any byte pattern is a valid `StreamingDecoder`.
+        let decoder = unsafe { &mut *(data.as_mut_ptr() as *mut Self) };
+        if !decoder.is_consistent() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(decoder)
+    }
+
+    /// Whether `feed` can resume from this state without indexing out of bounds or
+    /// reporting output it never wrote.
+    #[inline(always)]
+    fn is_consistent(&self) -> bool {
+        let resumable = match self.phase {
+            PHASE_HEADER | PHASE_TREE => self.current_node == 0 && self.output_len == 0,
+            // Leaves have no children to follow, so the walk must stand on an
+            // internal node unless the whole tree is a single leaf
+            PHASE_BITS => {
+                self.tree.is_complete()
+                    && self.output_len < self.original_len
+                    && (self.current_node as usize) < self.tree.node_count()
+                    && (self.tree.nodes[0].is_leaf()
+                        || !self.tree.nodes[self.current_node as usize].is_leaf())
+            }
+            PHASE_DONE => self.output_len == self.original_len,
+            _ => false,
+        };
+
+        resumable && self.header_len as usize <= MAX_HEADER_LEN && self.tree.is_consistent()
+    }
+
+    /// Starts decoding a new payload.
+    #[inline(always)]
+    pub fn reset(&mut self, options: DecodeOptions) {
+        self.tree.reset();
+        self.header_len = 0;
+        self.phase = PHASE_HEADER;
+        self.strict = options.strict as u8;
+        self.original_len = 0;
+        self.tree_remaining = 0;
+        self.current_node = 0;
+        self.output_len = 0;
+    }
+
+    /// Number of bytes written to the output so far.
+    #[inline(always)]
+    pub fn output_len(&self) -> usize {
+        self.output_len as usize
+    }
+
+    #[inline(always)]
+    pub fn is_complete(&self) -> bool {
+        self.phase == PHASE_DONE
+    }
+
+    /// Feeds the next chunk of the payload, decoding into `out`.
+    ///
+    /// Returns `Some(len)` once all `original_len` bytes have been decoded and
+    /// `None` while more data is needed.
+    pub fn feed(&mut self, chunk: &[u8], out: &mut [u8]) -> Result<Option<usize>, DecodeError> {
+        let mut chunk = chunk;
+
+        while let Some((&byte, rest)) = chunk.split_first() {
+            match self.phase {
+                PHASE_HEADER => {
+                    chunk = rest;
+                    self.push_header(byte, out.len())?;
+                }
+                PHASE_TREE => {
+                    let take = chunk.len().min(self.tree_remaining as usize);
+                    for &tree_byte in &chunk[..take] {
+                        self.tree.push(tree_byte)?;
+                    }
+                    chunk = &chunk[take..];
+                    self.tree_remaining -= take as u16;
+
+                    if self.tree_remaining == 0 {
+                        if !self.tree.is_complete() {
+                            return Err(DecodeError::MalformedTree);
+                        }
+                        self.phase = if self.original_len == 0 {
+                            PHASE_DONE
+                        } else {
+                            PHASE_BITS
+                        };
+                    }
+                }
+                PHASE_BITS => {
+                    chunk = rest;
+                    if self.push_bits(byte, out)? {
+                        self.phase = PHASE_DONE;
+                    }
+                }
+                _ => {
+                    if self.strict != 0 {
+                        return Err(DecodeError::TrailingData);
+                    }
+                    break;
+                }
+            }
+        }
+
+        Ok(self.is_complete().then_some(self.original_len as usize))
+    }
+
+    #[inline(always)]
+    fn push_header(&mut self, byte: u8, capacity: usize) -> Result<(), DecodeError> {
+        // Once complete, a header that failed to parse fails again on every call
+        // instead of growing past the buffer
+        if self.header_len == 0
+            || (self.header_len as usize) < Header::encoded_len_for(self.header[0])
+        {
+            let Some(slot) = self.header.get_mut(self.header_len as usize) else {
+                return Err(DecodeError::TruncatedHeader);
+            };
+            *slot = byte;
+            self.header_len += 1;
+        }
+
+        let header_len = Header::encoded_len_for(self.header[0]);
+        if (self.header_len as usize) < header_len {
+            return Ok(());
+        }
+
+        let (header, _) = Header::parse(&self.header[..header_len])?;
+        if header.mode() != MODE_TREE || header.is_eos() || header.format & FLAG_CHECKSUM != 0 {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+        if self.strict != 0 && header.is_needlessly_wide() {
+            return Err(DecodeError::NeedlesslyWide);
+        }
+        if header.original_len > capacity {
+            return Err(DecodeError::OutputOverflow);
+        }
+        if header.tree_size == 0 {
+            return Err(DecodeError::MalformedTree);
+        }
+
+        self.original_len = header.original_len as u16;
+        self.tree_remaining = header.tree_size as u16;
+        self.phase = PHASE_TREE;
+        Ok(())
+    }
+
+    /// Decodes the bits of one byte, returning `true` once the output is complete.
+    #[inline(always)]
+    fn push_bits(&mut self, byte: u8, out: &mut [u8]) -> Result<bool, DecodeError> {
+        let nodes = &self.tree.nodes;
+        let root = nodes[0];
+
+        // A single symbol tree encodes every occurrence as a zero bit
+        if root.is_leaf() && self.strict != 0 && byte != 0 {
+            return Err(DecodeError::NonZeroPadding);
+        }
+
+        let mut bits = byte;
+        for _ in 0..8 {
+            let next = if root.is_leaf() {
+                root
+            } else {
+                let node = nodes[self.current_node as usize];
+                self.current_node = if bits & 0x80 == 0 {
+                    node.left
+                } else {
+                    node.right
+                };
+                nodes[self.current_node as usize]
+            };
+            bits <<= 1;
+
+            if next.is_leaf() {
+                *out.get_mut(self.output_len as usize)
+                    .ok_or(DecodeError::OutputOverflow)? = next.byte_value;
+                self.output_len += 1;
+                self.current_node = 0;
+
+                if self.output_len == self.original_len {
+                    if self.strict != 0 && bits != 0 {
+                        return Err(DecodeError::NonZeroPadding);
+                    }
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,50 @@
+use crate::instruction::DecodeError;
+
+/// Validates `bytes` as UTF-8 (RFC 3629) and returns them as a `&str`.
+///
+/// URLs are short and mostly ASCII, so a byte-at-a-time walk with a single branch
+/// per ASCII byte is cheaper on SBF than the word-at-a-time setup of
+/// `core::str::from_utf8`.
+#[inline(always)]
+pub fn validate_utf8(bytes: &[u8]) -> Result<&str, DecodeError> {
+    let len = bytes.len();
+    let mut i = 0;
+
+    while i < len {
+        let lead = bytes[i];
+        if lead < 0x80 {
+            i += 1;
+            continue;
+        }
+
+        // Width and allowed range of the second byte, rejecting overlong forms,
+        // surrogates and code points above U+10FFFF
+        let (width, second_min, second_max) = match lead {
+            0xc2..=0xdf => (2, 0x80, 0xbf),
+            0xe0 => (3, 0xa0, 0xbf),
+            0xe1..=0xec | 0xee..=0xef => (3, 0x80, 0xbf),
+            0xed => (3, 0x80, 0x9f),
+            0xf0 => (4, 0x90, 0xbf),
+            0xf1..=0xf3 => (4, 0x80, 0xbf),
+            0xf4 => (4, 0x80, 0x8f),
+            _ => return Err(DecodeError::InvalidUtf8),
+        };
+        if len - i < width {
+            return Err(DecodeError::InvalidUtf8);
+        }
+
+        let second = bytes[i + 1];
+        if second < second_min || second > second_max {
+            return Err(DecodeError::InvalidUtf8);
+        }
+        for &continuation in &bytes[i + 2..i + width] {
+            if continuation & 0xc0 != 0x80 {
+                return Err(DecodeError::InvalidUtf8);
+            }
+        }
+        i += width;
+    }
+
+    // SAFETY: every sequence was checked above
+    Ok(unsafe { core::str::from_utf8_unchecked(bytes) })
+}
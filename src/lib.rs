@@ -3,6 +3,9 @@
 
 #[cfg(feature = "std")]
 extern crate std;
+/// Client side encoder, building payloads with the decoder's [`instruction::format`].
+#[cfg(feature = "client")]
+pub mod encoder;
 #[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
 pub mod instruction;
@@ -1,49 +1,68 @@
+// Most tests build their payloads with the encoder, see the `client` feature
+#![cfg_attr(not(feature = "client"), allow(unused_imports))]
+
 use mollusk_svm::Mollusk;
 use solana_sdk::pubkey::Pubkey;
 extern crate alloc;
 use alloc::vec;
-pub mod encoder;
 use std::fs::File;
 use std::io::Write;
 
-use crate::encoder::huffman_encode_url;
+#[cfg(feature = "client")]
+use solana_huffman_encoding_challenge::encoder::{
+    huffman_encode_batch, huffman_encode_url, huffman_encode_url_auto,
+    huffman_encode_url_auto_stats, huffman_encode_url_canonical,
+    huffman_encode_url_length_prefixed, huffman_encode_url_packed, huffman_encode_url_static,
+    huffman_encode_url_stored, with_checksum, EncodeError, HuffmanEncoder,
+};
+use solana_huffman_encoding_challenge::instruction::{
+    batch::huffman_decode_batch,
+    checksum::{url_checksum, CHECKSUM_LEN},
+    decoder::{
+        huffman_decode_url_into, stored_url, try_huffman_decode_url, try_huffman_decode_url_strict,
+        try_huffman_decode_url_with_capacity, try_huffman_decode_url_with_options, DecodeOptions,
+    },
+    format::{
+        Header, FLAG_CHECKSUM, FLAG_EOS, FLAG_WIDE, MODE_BATCH, MODE_CANONICAL, MODE_PACKED_TREE,
+        MODE_SHARED, MODE_STATIC, MODE_STORED, MODE_TREE,
+    },
+    shared_table::{
+        huffman_decode_url_into_with_table, shared_table_len, try_huffman_decode_url_with_table,
+        write_shared_table, PublishTable, SharedTable, TABLE_SEED,
+    },
+    static_table::STATIC_TABLE,
+    streaming::StreamingDecoder,
+    utf8::validate_utf8,
+    DecodeError, MAX_URL_LEN,
+};
 
+#[cfg(feature = "client")]
 pub const PROGRAM: Pubkey = Pubkey::new_from_array(solana_huffman_encoding_challenge::ID);
 
+#[cfg(feature = "client")]
 pub fn mollusk() -> Mollusk {
     Mollusk::new(&PROGRAM, "target/deploy/solana_huffman_encoding_challenge")
 }
 
-pub fn create_instruction_data(encoded_url: &[u8], original_size: u32) -> Vec<u8> {
-    let mut data = Vec::new();
-
-    // Format: [original_len: 1][tree_size: 2][tree_data][encoded_bits]
-    data.push(original_size as u8); // Single byte for original length
-
-    // Extract tree size from encoded data (first 4 bytes as u32, convert to u16)
-    let tree_size = u16::from_le_bytes([encoded_url[0], encoded_url[1]]);
-
-    // data.extend_from_slice(&tree_size.to_le_bytes()); // u16 tree size
-    data.push(tree_size as u8); // Single byte for tree size (assuming <= 255)
-    data.extend_from_slice(&encoded_url[2..]); // Rest of encoded data (tree + bits)
-    data
-}
+#[cfg(feature = "client")]
+const CHALLENGE_URLS: [&str; 10] = [
+    "http://localhost:3000",
+    "http://subdomain.localhost:3000",
+    "https://localhost.net",
+    "https://google.com",
+    "https://a.a",
+    "https://a.com",
+    "https://git@github.com:username/repo.git",
+    "https://a-really-long-url-that-probably-would-be-so-hard-to-actually-use-but-whatever.com",
+    "https://🦝👀🍹🌏.net",
+    "https://something.yourcooldomain.com?query_param=123&val=true",
+];
 
+#[cfg(feature = "client")]
 #[test]
 pub fn test_all_challenge_urls_and_store_metrics() {
     let mollusk = mollusk();
-    let test_urls = vec![
-        "http://localhost:3000",
-        "http://subdomain.localhost:3000",
-        "https://localhost.net",
-        "https://google.com",
-        "https://a.a",
-        "https://a.com",
-        "https://git@github.com:username/repo.git",
-        "https://a-really-long-url-that-probably-would-be-so-hard-to-actually-use-but-whatever.com",
-        "https://🦝👀🍹🌏.net",
-        "https://something.yourcooldomain.com?query_param=123&val=true",
-    ];
+    let test_urls = CHALLENGE_URLS;
 
     // Prepare CSV header
     let mut report = String::new();
@@ -53,18 +72,17 @@ pub fn test_all_challenge_urls_and_store_metrics() {
 
     for test_url in test_urls {
         let original_size = test_url.len();
-        let encoded_url = huffman_encode_url(test_url);
-        let compressed_size = encoded_url.len();
+        let instruction_data = huffman_encode_url(test_url);
+        let compressed_size = instruction_data.len();
         let compression_ratio = (original_size as f64) / (compressed_size as f64);
 
-        let instruction_data = create_instruction_data(&encoded_url, test_url.len() as u32);
         let ix_accounts = vec![];
         let ix = solana_sdk::instruction::Instruction::new_with_bytes(
             PROGRAM,
             &instruction_data,
             ix_accounts.clone(),
         );
-        let tx_accounts = &vec![];
+        let tx_accounts = &[];
 
         let result = mollusk.process_and_validate_instruction(
             &ix,
@@ -93,3 +111,1384 @@ pub fn test_all_challenge_urls_and_store_metrics() {
     file.write_all(report.as_bytes())
         .expect("failed to write metrics to file");
 }
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_checked_decode_round_trips_challenge_urls() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = huffman_encode_url(test_url);
+
+        let decoded = try_huffman_decode_url(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_checked_decode_handles_urls_longer_than_128_bytes() {
+    let mut long_url = String::from("https://gateway.example.com/ipfs/");
+    while long_url.len() < 400 {
+        long_url.push_str("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/");
+    }
+    let instruction_data = huffman_encode_url(&long_url);
+
+    let decoded = try_huffman_decode_url(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), long_url.as_bytes());
+}
+
+/// Needs the program built with `unchecked-decode` too, whose unchecked path only
+/// has room for 128 bytes.
+#[cfg(all(feature = "client", feature = "unchecked-decode"))]
+#[test]
+pub fn test_unchecked_entrypoint_decodes_urls_longer_than_128_bytes_checked() {
+    let mollusk = mollusk();
+    let mut long_url = String::from("https://gateway.example.com/ipfs/");
+    while long_url.len() < 400 {
+        long_url.push_str("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/");
+    }
+    let instruction_data = huffman_encode_url_length_prefixed(&long_url);
+    assert_eq!(instruction_data[0], MODE_TREE | FLAG_WIDE);
+
+    let ix =
+        solana_sdk::instruction::Instruction::new_with_bytes(PROGRAM, &instruction_data, vec![]);
+    let result = mollusk.process_and_validate_instruction(
+        &ix,
+        &[],
+        &[mollusk_svm::result::Check::success()],
+    );
+    assert_eq!(
+        result.program_result,
+        mollusk_svm::result::ProgramResult::Success
+    );
+}
+
+/// A short URL whose tree outgrows the unchecked path's 64 entry node table must be
+/// decoded checked as well.
+#[cfg(all(feature = "client", feature = "unchecked-decode"))]
+#[test]
+pub fn test_unchecked_entrypoint_decodes_large_trees_checked() {
+    let mollusk = mollusk();
+    let url = "https://abcdefghijklmnopqrstuvwxyz0123456789.com";
+    let instruction_data = huffman_encode_url(url);
+    let (header, _) = Header::parse(&instruction_data).unwrap();
+    assert_eq!(header.format, MODE_TREE);
+    assert!(header.tree_size > 64);
+
+    let ix =
+        solana_sdk::instruction::Instruction::new_with_bytes(PROGRAM, &instruction_data, vec![]);
+    let result = mollusk.process_and_validate_instruction(
+        &ix,
+        &[],
+        &[mollusk_svm::result::Check::success()],
+    );
+    assert_eq!(
+        result.program_result,
+        mollusk_svm::result::ProgramResult::Success
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_decode_into_caller_buffer() {
+    let instruction_data = huffman_encode_url("https://google.com");
+
+    // Only the decoded prefix of the buffer is touched
+    let mut account_data = [0xaa; 32];
+    let len = huffman_decode_url_into(&instruction_data, &mut account_data).unwrap();
+    assert_eq!(&account_data[..len], b"https://google.com");
+    assert!(account_data[len..].iter().all(|&byte| byte == 0xaa));
+
+    let mut exact = [0; 18];
+    assert_eq!(
+        huffman_decode_url_into(&instruction_data, &mut exact),
+        Ok(18)
+    );
+
+    let mut short = [0; 17];
+    assert_eq!(
+        huffman_decode_url_into(&instruction_data, &mut short),
+        Err(DecodeError::OutputOverflow)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_streaming_decoder_matches_one_shot_decode() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = huffman_encode_url_length_prefixed(test_url);
+
+        for chunk_size in [1, 2, 3, 7, instruction_data.len()] {
+            let mut decoder = Box::new(StreamingDecoder::new(DecodeOptions::STRICT));
+            let mut out = [0u8; 128];
+            let mut result = None;
+            for chunk in instruction_data.chunks(chunk_size) {
+                assert_eq!(result, None);
+                result = decoder.feed(chunk, &mut out).unwrap();
+            }
+            assert_eq!(result, Some(test_url.len()));
+            assert_eq!(&out[..test_url.len()], test_url.as_bytes());
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_streaming_decoder_resumes_from_account_data() {
+    let url = "https://something.yourcooldomain.com?query_param=123&val=true";
+    let instruction_data = huffman_encode_url_length_prefixed(url);
+    let (first, second) = instruction_data.split_at(instruction_data.len() / 2);
+
+    // u64 backing storage gives the same alignment as real account data
+    let mut account = vec![0u64; StreamingDecoder::LEN.div_ceil(8)];
+    let account_data = unsafe {
+        core::slice::from_raw_parts_mut(account.as_mut_ptr() as *mut u8, account.len() * 8)
+    };
+    let mut out = [0u8; 128];
+
+    let decoder = StreamingDecoder::from_account_data(account_data).unwrap();
+    decoder.reset(DecodeOptions::STRICT);
+    assert_eq!(decoder.feed(first, &mut out), Ok(None));
+
+    // A later transaction loads the same account and finishes the URL
+    let decoder = StreamingDecoder::from_account_data(account_data).unwrap();
+    assert_eq!(decoder.feed(second, &mut out), Ok(Some(url.len())));
+    assert_eq!(&out[..url.len()], url.as_bytes());
+    assert_eq!(decoder.feed(&[0], &mut out), Err(DecodeError::TrailingData));
+
+    assert!(StreamingDecoder::from_account_data(&mut account_data[..8]).is_err());
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_streaming_decoder_rejects_corrupt_account_data() {
+    use pinocchio::program_error::ProgramError;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let url = "https://something.yourcooldomain.com?query_param=123&val=true";
+    let instruction_data = huffman_encode_url_length_prefixed(url);
+    let mut account = vec![0u64; StreamingDecoder::LEN.div_ceil(8)];
+    let account_data = unsafe {
+        core::slice::from_raw_parts_mut(account.as_mut_ptr() as *mut u8, account.len() * 8)
+    };
+
+    // Garbage, e.g. a header length past the header buffer
+    account_data.fill(0xff);
+    assert_eq!(
+        StreamingDecoder::from_account_data(account_data).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+
+    // The fields after the node table end with `original_len`, `tree_remaining`,
+    // `current_node` and `output_len`, each a `u16`, and nodes are 6 bytes each,
+    // `[leaf: 1][byte_value: 1][left: 2][right: 2]`
+    let current_node_pos = StreamingDecoder::LEN - 4;
+    let output_len_pos = StreamingDecoder::LEN - 2;
+
+    // The walk resumed on a leaf, whose links were never range checked
+    let mut out = [0u8; 128];
+    account_data.fill(0);
+    let decoder = StreamingDecoder::from_account_data(account_data).unwrap();
+    decoder.reset(DecodeOptions::STRICT);
+    let (first, _) = instruction_data.split_at(instruction_data.len() / 2);
+    decoder.feed(first, &mut out).unwrap();
+    let leaf = (1..).find(|&i| account_data[i * 6] == 1).unwrap();
+    account_data[leaf * 6 + 2..leaf * 6 + 6].fill(0xff);
+    account_data[current_node_pos..current_node_pos + 2]
+        .copy_from_slice(&(leaf as u16).to_le_bytes());
+    assert_eq!(
+        StreamingDecoder::from_account_data(account_data).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+
+    // A finished decoder claiming output it never wrote
+    account_data.fill(0);
+    let decoder = StreamingDecoder::from_account_data(account_data).unwrap();
+    decoder.reset(DecodeOptions::STRICT);
+    assert_eq!(
+        decoder.feed(&instruction_data, &mut out).unwrap(),
+        Some(url.len())
+    );
+    account_data[output_len_pos..output_len_pos + 2].copy_from_slice(&1u16.to_le_bytes());
+    assert_eq!(
+        StreamingDecoder::from_account_data(account_data).err(),
+        Some(ProgramError::InvalidAccountData)
+    );
+
+    // Corrupt the state at every phase: loading either fails or leaves a decoder
+    // that can be fed without panicking
+    let mut rng = StdRng::seed_from_u64(7);
+    for split in [
+        0,
+        2,
+        3,
+        10,
+        instruction_data.len() / 2,
+        instruction_data.len(),
+    ] {
+        let (first, second) = instruction_data.split_at(split);
+        let mut out = [0u8; 128];
+        account_data.fill(0);
+        let decoder = StreamingDecoder::from_account_data(account_data).unwrap();
+        decoder.reset(DecodeOptions::STRICT);
+        decoder.feed(first, &mut out).unwrap();
+        let snapshot = account_data.to_vec();
+
+        for _ in 0..2000 {
+            account_data.copy_from_slice(&snapshot);
+            for _ in 0..rng.gen_range(1..4) {
+                // Half the time in the counters and indices after the node table
+                let start = if rng.gen() {
+                    0
+                } else {
+                    StreamingDecoder::LEN - 48
+                };
+                let pos = rng.gen_range(start..StreamingDecoder::LEN);
+                account_data[pos] = rng.gen();
+            }
+            if let Ok(decoder) = StreamingDecoder::from_account_data(account_data) {
+                let _ = decoder.feed(second, &mut out);
+                let _ = decoder.feed(&[0xff; 4], &mut out);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_streaming_decoder_keeps_failing_on_a_bad_header() {
+    let mut decoder = Box::new(StreamingDecoder::new(DecodeOptions::STRICT));
+    let mut out = [0u8; 128];
+    // 0xff announces a three byte header
+    assert_eq!(decoder.feed(&[0xff, 0xff], &mut out), Ok(None));
+    for _ in 0..5 {
+        assert_eq!(
+            decoder.feed(&[0xff, 0xff], &mut out),
+            Err(DecodeError::UnsupportedFormat)
+        );
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_encoder_switches_to_wide_header_for_large_trees() {
+    // 100 distinct bytes need a tree larger than 255 bytes
+    let url: String = (0..100u8).map(|i| (b'!' + i) as char).collect();
+    let instruction_data = huffman_encode_url_length_prefixed(&url);
+
+    let (header, _) = Header::parse(&instruction_data).unwrap();
+    assert!(header.is_wide());
+    assert_eq!(header.original_len, url.len());
+    assert!(header.tree_size > u8::MAX as usize);
+
+    let (header, _) = Header::parse(&huffman_encode_url_length_prefixed("https://a.com")).unwrap();
+    assert_eq!(header.format, MODE_TREE);
+}
+
+/// Re-encodes a compact payload with the wide layout, keeping its lengths.
+#[cfg(feature = "client")]
+fn widen(instruction_data: &[u8]) -> Vec<u8> {
+    let (header, rest) = Header::parse(instruction_data).unwrap();
+    let wide = Header {
+        format: header.format | FLAG_WIDE,
+        ..header
+    };
+    let mut widened = vec![0u8; wide.encoded_len()];
+    wide.write(&mut widened);
+    widened.extend_from_slice(rest);
+    widened
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_strict_mode_rejects_needlessly_wide_headers() {
+    let instruction_data = huffman_encode_url_length_prefixed("https://a.a");
+    let widened = widen(&instruction_data);
+    assert_eq!(
+        try_huffman_decode_url(&widened).unwrap().as_bytes(),
+        b"https://a.a"
+    );
+    assert_eq!(
+        try_huffman_decode_url_strict(&widened).err(),
+        Some(DecodeError::NeedlesslyWide)
+    );
+
+    let mut out = [0u8; 128];
+    assert_eq!(
+        StreamingDecoder::new(DecodeOptions::STRICT).feed(&widened, &mut out),
+        Err(DecodeError::NeedlesslyWide)
+    );
+
+    // Batch records carry their own lengths, which are widened as well:
+    // a tree of 'a' (code 0) and 'b' (code 1), then one record "ab"
+    let tree = [0, 1, b'a', 1, b'b'];
+    let mut compact = vec![MODE_BATCH, tree.len() as u8];
+    compact.extend_from_slice(&tree);
+    compact.extend_from_slice(&[1, 2, 0b0100_0000]);
+    let mut wide = vec![MODE_BATCH | FLAG_WIDE, tree.len() as u8, 0];
+    wide.extend_from_slice(&tree);
+    wide.extend_from_slice(&[1, 2, 0, 0b0100_0000]);
+
+    assert_eq!(
+        decode_batch(&compact, DecodeOptions::STRICT),
+        Ok(vec![b"ab".to_vec()])
+    );
+    assert_eq!(
+        decode_batch(&wide, DecodeOptions::default()),
+        Ok(vec![b"ab".to_vec()])
+    );
+    assert_eq!(
+        decode_batch(&wide, DecodeOptions::STRICT),
+        Err(DecodeError::NeedlesslyWide)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_eos_leaf_replaces_original_len() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = HuffmanEncoder::with_eos(test_url).encode(test_url);
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert!(header.is_eos());
+        assert_eq!(header.encoded_len(), 2);
+
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+        assert_eq!(
+            huffman_encode_url(test_url).len(),
+            instruction_data
+                .len()
+                .min(huffman_encode_url_length_prefixed(test_url).len())
+        );
+    }
+
+    // Long URLs with small trees keep the compact header
+    let long_url = "https://example.com/".repeat(20);
+    let instruction_data = HuffmanEncoder::with_eos(&long_url).encode(&long_url);
+    let (header, _) = Header::parse(&instruction_data).unwrap();
+    assert!(header.is_eos() && !header.is_wide());
+    let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), long_url.as_bytes());
+
+    let mut out = [0u8; 100];
+    assert_eq!(
+        huffman_decode_url_into(&instruction_data, &mut out),
+        Err(DecodeError::OutputOverflow)
+    );
+    assert_eq!(
+        StreamingDecoder::new(DecodeOptions::STRICT).feed(&instruction_data, &mut out),
+        Err(DecodeError::UnsupportedFormat)
+    );
+
+    // [FLAG_EOS][tree_size][0 2 1 'a'], "a" then EOS is `1 0`
+    let eos = FLAG_EOS | MODE_TREE;
+    let decode = |tree: &[u8], bits: &[u8]| {
+        let mut data = vec![eos, tree.len() as u8];
+        data.extend_from_slice(tree);
+        data.extend_from_slice(bits);
+        try_huffman_decode_url_strict(&data).map(|decoded| decoded.as_bytes().to_vec())
+    };
+    assert_eq!(decode(&[0, 2, 1, b'a'], &[0b1100_0000]), Ok(b"aa".to_vec()));
+    assert_eq!(
+        decode(&[0, 2, 1, b'a'], &[0b1111_1111]),
+        Err(DecodeError::BitstreamExhausted)
+    );
+    assert_eq!(
+        decode(&[0, 1, b'b', 1, b'a'], &[0]),
+        Err(DecodeError::MalformedTree)
+    );
+    assert_eq!(decode(&[0, 2, 2], &[0]), Err(DecodeError::DuplicateSymbol));
+    assert_eq!(decode(&[2], &[0]), Err(DecodeError::MalformedTree));
+    // Marker 2 needs FLAG_EOS, and FLAG_EOS needs MODE_TREE
+    assert_eq!(
+        try_huffman_decode_url(&[MODE_TREE, 1, 3, 0, 2, 1, b'a', 0]).err(),
+        Some(DecodeError::InvalidNodeMarker)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[eos | MODE_PACKED_TREE, 1, 0]).err(),
+        Some(DecodeError::UnsupportedFormat)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_checksum_trailer_is_verified_after_decoding() {
+    let corpus = CHALLENGE_URLS.concat();
+    let encoder = HuffmanEncoder::new(&corpus);
+    let publish_data = encoder.publish_table(0, 0);
+    let publish = PublishTable::parse(&publish_data).unwrap();
+    let mut account_data = vec![0u8; shared_table_len(publish.tree_data.len()).unwrap()];
+    write_shared_table(publish.tree_data, 0, &mut account_data).unwrap();
+    let table = SharedTable::from_account_data(&account_data).unwrap();
+
+    for test_url in CHALLENGE_URLS {
+        for instruction_data in [
+            huffman_encode_url(test_url),
+            huffman_encode_url_canonical(test_url).unwrap(),
+            huffman_encode_url_static(test_url),
+        ] {
+            let checked = with_checksum(instruction_data, test_url);
+            let decoded = try_huffman_decode_url_strict(&checked).unwrap();
+            assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+
+            // A well-formed payload for another URL fails only the checksum
+            let mut wrong = checked.clone();
+            let len = wrong.len();
+            wrong[len - CHECKSUM_LEN..].copy_from_slice(&url_checksum(b"https://evil.com"));
+            assert_eq!(
+                try_huffman_decode_url(&wrong).err(),
+                Some(DecodeError::ChecksumMismatch)
+            );
+        }
+
+        let checked = with_checksum(encoder.encode_shared(test_url), test_url);
+        let decoded =
+            try_huffman_decode_url_with_table(&checked, &table, DecodeOptions::STRICT).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+    }
+
+    assert_eq!(
+        try_huffman_decode_url(&[FLAG_CHECKSUM | MODE_STATIC, 0, 0]).err(),
+        Some(DecodeError::TruncatedHeader)
+    );
+    let checked = with_checksum(
+        huffman_encode_url_length_prefixed("https://a.com"),
+        "https://a.com",
+    );
+    let mut out = [0u8; 64];
+    assert_eq!(
+        StreamingDecoder::new(DecodeOptions::STRICT).feed(&checked, &mut out),
+        Err(DecodeError::UnsupportedFormat)
+    );
+}
+
+#[test]
+pub fn test_url_checksum_matches_sha256() {
+    // Lengths around the 55/56/64 byte padding boundaries of SHA-256 blocks
+    for len in 0..200usize {
+        let data: Vec<u8> = (0..len).map(|i| (i * 7 + len) as u8).collect();
+        let hash = solana_program::hash::hash(&data).to_bytes();
+        assert_eq!(url_checksum(&data), hash[..CHECKSUM_LEN]);
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_checked_decode_handles_large_alphabets() {
+    // Every printable ASCII byte plus multi-byte UTF-8 sequences
+    let mut url = String::from("https://例え.テスト/🦝👀🍹🌏?");
+    url.extend((b' '..=b'~').map(char::from));
+    let instruction_data = huffman_encode_url(&url);
+
+    let decoded = try_huffman_decode_url(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), url.as_bytes());
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_strict_decode_rejects_non_canonical_bit_streams() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = huffman_encode_url(test_url);
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+
+        // Extra bytes after the bit stream are only accepted by the lenient decoder
+        let mut trailing = instruction_data.clone();
+        trailing.push(0);
+        assert!(try_huffman_decode_url(&trailing).is_ok());
+        assert_eq!(
+            try_huffman_decode_url_strict(&trailing).err(),
+            Some(DecodeError::TrailingData)
+        );
+    }
+
+    let mut instruction_data = huffman_encode_url("https://google.com");
+    *instruction_data.last_mut().unwrap() |= 1;
+    let lenient = try_huffman_decode_url(&instruction_data).unwrap();
+    assert_eq!(lenient.as_bytes(), b"https://google.com");
+    assert_eq!(
+        try_huffman_decode_url_strict(&instruction_data).err(),
+        Some(DecodeError::NonZeroPadding)
+    );
+
+    // Single symbol trees encode every occurrence as a zero bit
+    assert!(try_huffman_decode_url_strict(&[0, 3, 2, 1, b'a', 0]).is_ok());
+    assert_eq!(
+        try_huffman_decode_url_strict(&[0, 3, 2, 1, b'a', 0x01]).err(),
+        Some(DecodeError::NonZeroPadding)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_canonical_mode_round_trips_with_smaller_tables() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = huffman_encode_url_canonical(test_url).unwrap();
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(header.mode(), MODE_CANONICAL);
+
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+        assert!(instruction_data.len() < huffman_encode_url(test_url).len());
+    }
+
+    let instruction_data = huffman_encode_url_canonical("aaaa").unwrap();
+    let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), b"aaaa");
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_packed_tree_round_trips_with_smaller_trees() {
+    let long_url = "https://example.com/".repeat(20);
+    let all_bytes: String = (0u8..128).map(char::from).collect();
+    for test_url in
+        CHALLENGE_URLS
+            .into_iter()
+            .chain([long_url.as_str(), all_bytes.as_str(), "aaaa"])
+    {
+        let instruction_data = huffman_encode_url_packed(test_url);
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(header.mode(), MODE_PACKED_TREE);
+
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+
+        let (byte_header, _) = Header::parse(&huffman_encode_url(test_url)).unwrap();
+        assert!(header.tree_size * 3 <= byte_header.tree_size * 2 + 3);
+    }
+}
+
+#[test]
+pub fn test_packed_tree_rejects_malformed_trees() {
+    let decode = |tree: &[u8], options: DecodeOptions| {
+        let mut data = vec![MODE_PACKED_TREE, 1, tree.len() as u8];
+        data.extend_from_slice(tree);
+        data.push(0);
+        try_huffman_decode_url_with_options::<MAX_URL_LEN>(&data, options).err()
+    };
+
+    // 0 1'a' 1'b': two leaves under the root, then 6 padding bits
+    let tree = [0b0101_1000, 0b0110_1100, 0b0100_0000];
+    assert_eq!(decode(&tree, DecodeOptions::STRICT), None);
+    assert_eq!(
+        decode(&[tree[0], tree[1], 0b0100_0001], DecodeOptions::STRICT),
+        Some(DecodeError::NonZeroPadding)
+    );
+    assert_eq!(
+        decode(&[tree[0], tree[1], 0b0100_0001], DecodeOptions::default()),
+        None
+    );
+    assert_eq!(
+        decode(&[tree[0], tree[1], tree[2], 0], DecodeOptions::default()),
+        Some(DecodeError::TrailingTreeData)
+    );
+    assert_eq!(
+        decode(&tree[..2], DecodeOptions::default()),
+        Some(DecodeError::MalformedTree)
+    );
+    // Second leaf repeats 'a'
+    assert_eq!(
+        decode(
+            &[0b0101_1000, 0b0110_1100, 0b0010_0000],
+            DecodeOptions::default()
+        ),
+        Some(DecodeError::DuplicateSymbol)
+    );
+}
+
+#[test]
+pub fn test_canonical_mode_rejects_invalid_tables() {
+    let decode = |table: &[u8]| {
+        let mut data = vec![MODE_CANONICAL, 1, table.len() as u8];
+        data.extend_from_slice(table);
+        data.push(0);
+        try_huffman_decode_url(&data).err()
+    };
+
+    // Three one-bit codes oversubscribe the code space
+    assert_eq!(
+        decode(&[1, 3, b'a', b'b', b'c']),
+        Some(DecodeError::InvalidCodeTable)
+    );
+    // One one-bit and one two-bit code leave a code unused
+    assert_eq!(
+        decode(&[2, 1, 1, b'a', b'b']),
+        Some(DecodeError::InvalidCodeTable)
+    );
+    // Unused longest length
+    assert_eq!(
+        decode(&[2, 2, 0, b'a', b'b']),
+        Some(DecodeError::InvalidCodeTable)
+    );
+    // Symbols of the same length out of order
+    assert_eq!(
+        decode(&[1, 2, b'b', b'a']),
+        Some(DecodeError::InvalidCodeTable)
+    );
+    assert_eq!(decode(&[17]), Some(DecodeError::InvalidCodeTable));
+    assert_eq!(
+        decode(&[1, 2, b'a', b'a']),
+        Some(DecodeError::DuplicateSymbol)
+    );
+    assert_eq!(decode(&[1, 2, b'a']), Some(DecodeError::MalformedTree));
+    assert_eq!(
+        decode(&[1, 2, b'a', b'b', b'c']),
+        Some(DecodeError::TrailingTreeData)
+    );
+    assert_eq!(decode(&[1, 2, b'a', b'b']), None);
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_static_mode_sends_no_tree() {
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = huffman_encode_url_static(test_url);
+        let (header, rest) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(header.mode(), MODE_STATIC);
+        assert_eq!(header.encoded_len(), 2);
+        assert_eq!(rest.len(), instruction_data.len() - 2);
+
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+        if test_url.is_ascii() {
+            assert!(instruction_data.len() < huffman_encode_url_canonical(test_url).unwrap().len());
+        }
+    }
+
+    let long_url = "https://example.com/".repeat(20);
+    let instruction_data = huffman_encode_url_static(&long_url);
+    assert_eq!(Header::parse(&instruction_data).unwrap().0.encoded_len(), 3);
+    let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), long_url.as_bytes());
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_static_mode_escapes_bytes_outside_the_table() {
+    for test_url in ["a\0b", "\t\x7f", "https://🦝.net/ünïcödé", "\0", "a b"] {
+        let instruction_data = huffman_encode_url_static(test_url);
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+    }
+
+    // The escape symbol sorts first among the 18 12-bit codes, so its code is
+    // 0xfff - 17 = 0xfee, and it needs 8 more bits for the literal
+    let data = [MODE_STATIC, 1, 0xfe, 0xe0];
+    assert_eq!(
+        try_huffman_decode_url(&data).err(),
+        Some(DecodeError::BitstreamExhausted)
+    );
+    // '~' has its own code, so escaping it is a second encoding of the same URL
+    assert_eq!(huffman_encode_url_static("~"), [MODE_STATIC, 1, 0xfe, 0xc0]);
+    let data = [MODE_STATIC, 1, 0xfe, 0xe0 | (b'~' >> 4), b'~' << 4];
+    assert_eq!(
+        try_huffman_decode_url_strict(&data).err(),
+        Some(DecodeError::RedundantEscape)
+    );
+    assert_eq!(try_huffman_decode_url(&data).unwrap().as_bytes(), b"~");
+}
+
+#[test]
+pub fn test_static_table_is_a_valid_canonical_table() {
+    let mut data = vec![MODE_CANONICAL, 0, STATIC_TABLE.len() as u8];
+    data.extend_from_slice(&STATIC_TABLE);
+    assert!(try_huffman_decode_url_strict(&data).is_ok());
+
+    // Every visible ASCII byte has its own code, space is escaped
+    let symbols = &STATIC_TABLE[1 + STATIC_TABLE[0] as usize..];
+    assert!((b'!'..=b'~').all(|byte| symbols.contains(&byte)));
+    assert!(!symbols.contains(&b' '));
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_publish_table_claims_a_pre_funded_pda() {
+    use solana_sdk::{account::Account, instruction::AccountMeta, system_program};
+
+    let mollusk = mollusk();
+    let encoder = HuffmanEncoder::new(&CHALLENGE_URLS.concat());
+    let authority = Pubkey::new_unique();
+    let (table, bump) = Pubkey::find_program_address(
+        &[TABLE_SEED, authority.as_ref(), &7u32.to_le_bytes()],
+        &PROGRAM,
+    );
+
+    // Anyone can send lamports to the PDA before it is published
+    for squatted_lamports in [0, 1] {
+        let ix = solana_sdk::instruction::Instruction::new_with_bytes(
+            PROGRAM,
+            &encoder.publish_table(7, bump),
+            vec![
+                AccountMeta::new(authority, true),
+                AccountMeta::new(table, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let accounts = [
+            (
+                authority,
+                Account::new(1_000_000_000, 0, &system_program::id()),
+            ),
+            (
+                table,
+                Account::new(squatted_lamports, 0, &system_program::id()),
+            ),
+            mollusk_svm::program::keyed_account_for_system_program(),
+        ];
+
+        let result = mollusk.process_and_validate_instruction(
+            &ix,
+            &accounts,
+            &[mollusk_svm::result::Check::success()],
+        );
+        let table_account = result.get_account(&table).unwrap();
+        assert_eq!(table_account.owner, PROGRAM);
+        let table_data = SharedTable::from_account_data(&table_account.data).unwrap();
+        assert_eq!(table_data.table_id(), 7);
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_shared_table_decodes_payloads_without_a_tree() {
+    let corpus = CHALLENGE_URLS.concat();
+    let encoder = HuffmanEncoder::new(&corpus);
+
+    let publish_data = encoder.publish_table(7, 254);
+    let publish = PublishTable::parse(&publish_data).unwrap();
+    assert_eq!((publish.table_id, publish.bump), (7, 254));
+
+    let mut account_data = vec![0u8; shared_table_len(publish.tree_data.len()).unwrap()];
+    write_shared_table(publish.tree_data, publish.table_id, &mut account_data).unwrap();
+    let table = SharedTable::from_account_data(&account_data).unwrap();
+    assert_eq!(table.table_id(), 7);
+
+    for test_url in CHALLENGE_URLS {
+        let instruction_data = encoder.encode_shared(test_url);
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(header.mode(), MODE_SHARED);
+
+        let decoded =
+            try_huffman_decode_url_with_table(&instruction_data, &table, DecodeOptions::STRICT)
+                .unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+        assert!(instruction_data.len() < huffman_encode_url(test_url).len());
+
+        // The plain decoders have no table to decode with
+        assert_eq!(
+            try_huffman_decode_url(&instruction_data).err(),
+            Some(DecodeError::UnsupportedFormat)
+        );
+    }
+
+    let mut out = [0u8; 4];
+    let instruction_data = encoder.encode_shared(CHALLENGE_URLS[0]);
+    assert_eq!(
+        huffman_decode_url_into_with_table(
+            &instruction_data,
+            &table,
+            &mut out,
+            DecodeOptions::STRICT
+        ),
+        Err(DecodeError::OutputOverflow)
+    );
+}
+
+#[test]
+pub fn test_shared_table_rejects_invalid_tables() {
+    assert_eq!(shared_table_len(3), Err(DecodeError::MalformedTree));
+    assert_eq!(
+        shared_table_len(3 * 257 - 1),
+        Err(DecodeError::TreeTooLarge)
+    );
+
+    // Right size for two leaves, but the second symbol repeats the first
+    let tree = [0, 1, b'a', 1, b'a'];
+    let mut account_data = vec![0u8; shared_table_len(tree.len()).unwrap()];
+    assert_eq!(
+        write_shared_table(&tree, 0, &mut account_data),
+        Err(DecodeError::DuplicateSymbol)
+    );
+    assert_eq!(
+        write_shared_table(&[0, 1, b'a', 1, b'b'], 0, &mut account_data[..8]),
+        Err(DecodeError::InvalidSharedTable)
+    );
+
+    // Never written, so the tag is missing
+    assert_eq!(
+        SharedTable::from_account_data(&account_data).err(),
+        Some(DecodeError::InvalidSharedTable)
+    );
+    assert_eq!(
+        SharedTable::from_account_data(&[1, 0, 0, 0, 0, 0, 0, 0]).err(),
+        Some(DecodeError::InvalidSharedTable)
+    );
+    assert_eq!(
+        PublishTable::parse(&[MODE_TREE, 0, 0, 0, 0, 255]),
+        Err(DecodeError::UnsupportedFormat)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_checked_decode_rejects_malformed_input() {
+    assert_eq!(
+        try_huffman_decode_url(&[]).err(),
+        Some(DecodeError::TruncatedHeader)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[0, 4]).err(),
+        Some(DecodeError::TruncatedHeader)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[0, 4, 200, 0, 1]).err(),
+        Some(DecodeError::TruncatedTree)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[0x10, 0xff, 0xff, 2, 0, 1, b'a', 0]).err(),
+        Some(DecodeError::OutputOverflow)
+    );
+    // Leaf marker without its symbol byte
+    assert_eq!(
+        try_huffman_decode_url(&[0, 1, 1, 1]).err(),
+        Some(DecodeError::MalformedTree)
+    );
+    // Internal node missing its right child
+    assert_eq!(
+        try_huffman_decode_url(&[0, 1, 3, 0, 1, b'a', 0]).err(),
+        Some(DecodeError::MalformedTree)
+    );
+    // Marker other than 0 or 1
+    assert_eq!(
+        try_huffman_decode_url(&[0, 1, 2, 2, b'a', 0]).err(),
+        Some(DecodeError::InvalidNodeMarker)
+    );
+    // Complete tree followed by extra bytes inside the tree region
+    assert_eq!(
+        try_huffman_decode_url(&[0, 1, 3, 1, b'a', 7, 0]).err(),
+        Some(DecodeError::TrailingTreeData)
+    );
+    // Two leaves for the same symbol
+    assert_eq!(
+        try_huffman_decode_url(&[0, 1, 5, 0, 1, b'a', 1, b'a', 0]).err(),
+        Some(DecodeError::DuplicateSymbol)
+    );
+    // 256 nested internal nodes overflow the parser stack
+    let mut deep = vec![0x10, 1, 0, 0, 1];
+    deep.extend_from_slice(&[0; 256]);
+    assert_eq!(
+        try_huffman_decode_url(&deep).err(),
+        Some(DecodeError::StackOverflow)
+    );
+    // 512 nodes do not fit the full alphabet node table
+    let mut wide = vec![0x10, 1, 0, 0, 3];
+    for symbol in 0..=255u8 {
+        wide.extend_from_slice(&[0, 1, symbol]);
+    }
+    assert_eq!(
+        try_huffman_decode_url(&wide).err(),
+        Some(DecodeError::TreeTooLarge)
+    );
+    // Header advertises more than the output capacity
+    assert_eq!(
+        try_huffman_decode_url_with_capacity::<16>(&[0, 17, 2, 1, b'a', 0, 0, 0]).err(),
+        Some(DecodeError::OutputOverflow)
+    );
+    // Unknown mode and unknown flag bits
+    assert_eq!(
+        try_huffman_decode_url(&[0x0f, 1, 2, 1, b'a', 0]).err(),
+        Some(DecodeError::UnsupportedFormat)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[0x80, 1, 2, 1, b'a', 0]).err(),
+        Some(DecodeError::UnsupportedFormat)
+    );
+    // Bit stream too short for the advertised length
+    let mut instruction_data = huffman_encode_url("https://google.com");
+    instruction_data.truncate(instruction_data.len() - 2);
+    assert_eq!(
+        try_huffman_decode_url(&instruction_data).err(),
+        Some(DecodeError::BitstreamExhausted)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_encoder_output_is_reproducible() {
+    // Ties between equal frequencies break by symbol, then by insertion order
+    assert_eq!(
+        huffman_encode_url_length_prefixed("https://a.a"),
+        [
+            0, 11, 23, 0, 0, 0, 1, b't', 1, b'a', 0, 1, b'/', 1, b's', 0, 0, 1, b'p', 1, b'h', 0,
+            1, b':', 1, b'.', 160, 71, 146, 60, 128
+        ]
+    );
+
+    for test_url in CHALLENGE_URLS {
+        let first = huffman_encode_url(test_url);
+        for _ in 0..20 {
+            assert_eq!(huffman_encode_url(test_url), first);
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_auto_mode_picks_the_smallest_format() {
+    let long_url = "https://example.com/".repeat(20);
+    for test_url in CHALLENGE_URLS.into_iter().chain([long_url.as_str()]) {
+        let instruction_data = huffman_encode_url_auto(test_url);
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), test_url.as_bytes());
+
+        let candidates = [
+            Some(huffman_encode_url(test_url)),
+            Some(huffman_encode_url_packed(test_url)),
+            huffman_encode_url_canonical(test_url),
+            Some(huffman_encode_url_static(test_url)),
+            Some(huffman_encode_url_stored(test_url)),
+        ];
+        let smallest = candidates.iter().flatten().map(Vec::len).min().unwrap();
+        assert_eq!(instruction_data.len(), smallest);
+        assert!(instruction_data.len() <= test_url.len() + 1);
+    }
+
+    let instruction_data = huffman_encode_url_auto("");
+    assert_eq!(instruction_data, [MODE_STORED]);
+    let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+    assert_eq!(decoded.as_bytes(), b"");
+
+    // Too short to pay for a tree
+    let instruction_data = huffman_encode_url_auto("https://a.a");
+    assert_eq!(instruction_data[0], MODE_STATIC);
+    assert!(instruction_data.len() < "https://a.a".len());
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_stored_mode_returns_bytes_verbatim() {
+    let test_url = "https://🦝👀🍹🌏.net";
+    let instruction_data = huffman_encode_url_stored(test_url);
+    assert_eq!(instruction_data[0], MODE_STORED);
+    assert_eq!(instruction_data.len(), test_url.len() + 1);
+    assert_eq!(huffman_encode_url_auto(test_url), instruction_data);
+
+    assert_eq!(stored_url(&instruction_data), Ok(test_url.as_bytes()));
+    let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+    assert_eq!(decoded.as_str(), Ok(test_url));
+
+    let checked = with_checksum(instruction_data.clone(), test_url);
+    assert_eq!(stored_url(&checked), Ok(test_url.as_bytes()));
+    let mut corrupted = checked.clone();
+    corrupted[1] ^= 1;
+    assert_eq!(stored_url(&corrupted), Err(DecodeError::ChecksumMismatch));
+
+    // Bytes come back as sent, UTF-8 validation is up to the caller
+    let raw = [MODE_STORED, 0xff, 0xfe];
+    assert_eq!(stored_url(&raw), Ok(&[0xff, 0xfe][..]));
+    let decoded = try_huffman_decode_url(&raw).unwrap();
+    assert_eq!(decoded.as_str(), Err(DecodeError::InvalidUtf8));
+
+    assert_eq!(stored_url(&[MODE_STORED]), Ok(&[][..]));
+    assert_eq!(
+        stored_url(&[MODE_STORED | FLAG_WIDE, b'a']),
+        Err(DecodeError::UnsupportedFormat)
+    );
+    assert_eq!(
+        try_huffman_decode_url(&[MODE_STORED | FLAG_WIDE, b'a']).err(),
+        Some(DecodeError::UnsupportedFormat)
+    );
+    let mut too_long = vec![MODE_STORED];
+    too_long.resize(MAX_URL_LEN + 2, b'a');
+    assert_eq!(stored_url(&too_long), Err(DecodeError::OutputOverflow));
+    assert_eq!(
+        try_huffman_decode_url(&too_long).err(),
+        Some(DecodeError::OutputOverflow)
+    );
+    assert_eq!(
+        stored_url(&huffman_encode_url(test_url)),
+        Err(DecodeError::UnsupportedFormat)
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_encoder_stats_match_the_encoding() {
+    for test_url in CHALLENGE_URLS {
+        for encoder in [
+            HuffmanEncoder::new(test_url),
+            HuffmanEncoder::with_eos(test_url),
+        ] {
+            let instruction_data = encoder.encode(test_url);
+            let stats = encoder.stats(test_url);
+
+            assert_eq!(stats.mode, MODE_TREE);
+            assert_eq!(stats.total_bytes, instruction_data.len());
+            let (header, rest) = Header::parse(&instruction_data).unwrap();
+            assert_eq!(stats.header_bytes, header.encoded_len());
+            assert_eq!(stats.tree_bytes, header.tree_size);
+            assert_eq!(
+                (stats.payload_bits + stats.padding_bits) / 8,
+                rest.len() - header.tree_size
+            );
+            assert!(stats.padding_bits < 8);
+            assert_eq!(stats.eos_code.is_some(), header.is_eos());
+            assert_eq!(
+                stats.compression_ratio,
+                Some(test_url.len() as f64 / instruction_data.len() as f64)
+            );
+
+            let symbol_bits: usize = stats
+                .symbols
+                .iter()
+                .map(|symbol| symbol.freq as usize * symbol.bit_length as usize)
+                .sum();
+            let eos_bits = stats
+                .eos_code
+                .map_or(0, |(_, bit_length)| bit_length as usize);
+            assert_eq!(symbol_bits + eos_bits, stats.payload_bits);
+            let freq_total: u32 = stats.symbols.iter().map(|symbol| symbol.freq).sum();
+            assert_eq!(freq_total as usize, test_url.len());
+            assert!(stats
+                .symbols
+                .windows(2)
+                .all(|pair| pair[0].byte < pair[1].byte));
+        }
+    }
+
+    // A tree costs more than this URL saves
+    assert!(
+        HuffmanEncoder::new("https://a.a")
+            .stats("https://a.a")
+            .compression_ratio
+            .unwrap()
+            < 1.0
+    );
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_auto_stats_match_the_picked_encoding() {
+    let mut modes = Vec::new();
+    let repeated = ["~".repeat(200), "~^".repeat(100), "{}|~^`\\".repeat(40)];
+    for test_url in CHALLENGE_URLS
+        .iter()
+        .copied()
+        .chain(["", "ab", "https://example.com/", "\u{1f600}"])
+        .chain(repeated.iter().map(String::as_str))
+    {
+        let instruction_data = huffman_encode_url_auto(test_url);
+        let stats = huffman_encode_url_auto_stats(test_url);
+
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(stats.mode, header.mode());
+        assert_eq!(stats.total_bytes, instruction_data.len());
+        assert_eq!(stats.header_bytes, header.encoded_len());
+        assert_eq!(stats.tree_bytes, header.tree_size);
+        assert_eq!(
+            stats.header_bytes + stats.tree_bytes + (stats.payload_bits + stats.padding_bits) / 8,
+            instruction_data.len()
+        );
+        assert!(stats.padding_bits < 8);
+        let freq_total: u32 = stats.symbols.iter().map(|symbol| symbol.freq).sum();
+        assert_eq!(freq_total as usize, test_url.len());
+        modes.push(stats.mode);
+    }
+
+    // The stats follow the format, not just the `encode` layout
+    for mode in [MODE_TREE, MODE_PACKED_TREE, MODE_STATIC, MODE_STORED] {
+        assert!(modes.contains(&mode), "mode {mode} never picked");
+    }
+
+    // The empty URL is stored as its format byte alone, with no ratio to report
+    let empty = huffman_encode_url_auto_stats("");
+    assert_eq!(empty.mode, MODE_STORED);
+    assert_eq!(empty.total_bytes, 1);
+    assert_eq!(empty.payload_bits, 0);
+    assert!(empty.symbols.is_empty());
+    assert_eq!(empty.compression_ratio, None);
+}
+
+#[cfg(feature = "client")]
+#[test]
+#[should_panic(expected = "URL longer than MAX_URL_LEN")]
+pub fn test_encode_panics_on_urls_longer_than_max_url_len() {
+    let url = "a".repeat(70000);
+    HuffmanEncoder::new(&url).encode(&url);
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_try_encode_rejects_bad_inputs() {
+    assert_eq!(
+        HuffmanEncoder::try_encode(b""),
+        Err(EncodeError::EmptyInput)
+    );
+    assert_eq!(
+        HuffmanEncoder::try_encode(&[b'a'; MAX_URL_LEN + 1]),
+        Err(EncodeError::InputTooLong)
+    );
+
+    for test_url in CHALLENGE_URLS {
+        let encoded = HuffmanEncoder::try_encode(test_url.as_bytes()).unwrap();
+        assert_eq!(encoded.as_bytes(), huffman_encode_url(test_url));
+    }
+
+    // The program would decode these, then fail the transaction on `as_str`
+    assert_eq!(
+        HuffmanEncoder::try_encode(&[0xff, 0xfe]),
+        Err(EncodeError::InvalidUtf8)
+    );
+    let all_bytes: Vec<u8> = (0..=u8::MAX).collect();
+    assert_eq!(
+        HuffmanEncoder::try_encode(&all_bytes),
+        Err(EncodeError::InvalidUtf8)
+    );
+
+    // Close to the widest UTF-8 alphabet, with skewed frequencies: both the length
+    // prefixed and the end-of-stream trees fit the decoder
+    let mut wide_alphabet: String = (0..=0x7fu8).map(char::from).collect();
+    wide_alphabet.extend((0x80..0x800).step_by(31).filter_map(char::from_u32));
+    for count in [1, 2, 4, 8, 16, 32, 64, 128, 256] {
+        wide_alphabet.extend(std::iter::repeat_n('a', count));
+    }
+    let encoded = HuffmanEncoder::try_encode(wide_alphabet.as_bytes()).unwrap();
+    let decoded = try_huffman_decode_url_strict(encoded.as_bytes()).unwrap();
+    assert_eq!(decoded.as_bytes(), wide_alphabet.as_bytes());
+
+    let longest = HuffmanEncoder::try_encode(&[b'a'; MAX_URL_LEN]).unwrap();
+    let decoded = try_huffman_decode_url_strict(&longest.into_bytes()).unwrap();
+    assert_eq!(decoded.as_bytes(), [b'a'; MAX_URL_LEN]);
+}
+
+/// Longest code of a pre-order serialized tree.
+#[cfg(feature = "client")]
+fn serialized_tree_depth(tree: &[u8]) -> usize {
+    let mut pending = vec![0];
+    let mut bytes = tree.iter();
+    let mut max_depth = 0;
+    while let Some(depth) = pending.pop() {
+        match bytes.next() {
+            Some(0) => pending.extend([depth + 1, depth + 1]),
+            Some(1) => {
+                bytes.next();
+                max_depth = max_depth.max(depth);
+            }
+            _ => panic!("malformed tree"),
+        }
+    }
+    max_depth
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_length_limited_codes_round_trip() {
+    // Fibonacci frequencies give the deepest Huffman tree for their size
+    let (mut a, mut b) = (1, 1);
+    let mut skewed = String::new();
+    for symbol in b'a'..=b'm' {
+        skewed.extend(core::iter::repeat_n(symbol as char, a));
+        (a, b) = (b, a + b);
+    }
+
+    let encode = |max_code_len| {
+        let instruction_data =
+            HuffmanEncoder::with_max_code_len(&skewed, max_code_len).encode(&skewed);
+        let decoded = try_huffman_decode_url_strict(&instruction_data).unwrap();
+        assert_eq!(decoded.as_bytes(), skewed.as_bytes());
+
+        let (header, rest) = Header::parse(&instruction_data).unwrap();
+        let depth = serialized_tree_depth(&rest[..header.tree_size]);
+        (instruction_data.len(), depth)
+    };
+
+    let (unlimited_len, unlimited_depth) = encode(32);
+    assert_eq!(unlimited_depth, 12);
+    for max_code_len in 4..12 {
+        let (len, depth) = encode(max_code_len);
+        assert_eq!(depth, max_code_len);
+        assert!(len >= unlimited_len);
+    }
+
+    // Four symbols in two bits each, whatever their frequencies
+    let instruction_data =
+        HuffmanEncoder::with_max_code_len("abccdddddddd", 2).encode("abccdddddddd");
+    let (header, rest) = Header::parse(&instruction_data).unwrap();
+    assert_eq!(serialized_tree_depth(&rest[..header.tree_size]), 2);
+    assert_eq!(
+        try_huffman_decode_url_strict(&instruction_data)
+            .unwrap()
+            .as_bytes(),
+        b"abccdddddddd"
+    );
+}
+
+#[cfg(feature = "client")]
+fn decode_batch(
+    instruction_data: &[u8],
+    options: DecodeOptions,
+) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let mut urls = Vec::new();
+    let count = huffman_decode_batch(instruction_data, options, |url| {
+        urls.push(url.to_vec());
+        Ok(())
+    })?;
+    assert_eq!(count, urls.len());
+    Ok(urls)
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_batch_decodes_every_url_with_one_tree() {
+    let long_url = "https://example.com/".repeat(20);
+    let all_bytes: String = (0u8..128).map(char::from).collect();
+    let batches: [&[&str]; 4] = [
+        &CHALLENGE_URLS,
+        &[long_url.as_str(), "https://example.com", ""],
+        &[all_bytes.as_str(), "https://example.com"],
+        &["aaaa", "a", "aaaaaaaaa"],
+    ];
+
+    for urls in batches {
+        let instruction_data = huffman_encode_batch(urls);
+        let (header, _) = Header::parse(&instruction_data).unwrap();
+        assert_eq!(header.mode(), MODE_BATCH);
+
+        let decoded = decode_batch(&instruction_data, DecodeOptions::STRICT).unwrap();
+        let expected: Vec<&[u8]> = urls.iter().map(|url| url.as_bytes()).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    // The tree is only paid for once
+    let separate: usize = CHALLENGE_URLS
+        .iter()
+        .map(|url| huffman_encode_url(url).len())
+        .sum();
+    assert!(huffman_encode_batch(&CHALLENGE_URLS).len() < separate);
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_batch_rejects_malformed_records() {
+    let instruction_data = huffman_encode_batch(&["https://example.com", "https://a.io"]);
+
+    let mut trailing = instruction_data.clone();
+    trailing.push(0);
+    assert_eq!(
+        decode_batch(&trailing, DecodeOptions::STRICT),
+        Err(DecodeError::TrailingData)
+    );
+    assert!(decode_batch(&trailing, DecodeOptions::default()).is_ok());
+
+    assert_eq!(
+        decode_batch(
+            &instruction_data[..instruction_data.len() - 1],
+            DecodeOptions::default()
+        ),
+        Err(DecodeError::BitstreamExhausted)
+    );
+
+    // Header and tree only, no record count
+    let (header, _) = Header::parse(&instruction_data).unwrap();
+    let tree_end = header.encoded_len() + header.tree_size;
+    assert_eq!(
+        decode_batch(&instruction_data[..tree_end], DecodeOptions::default()),
+        Err(DecodeError::TruncatedHeader)
+    );
+    // Second record length missing
+    let mut one_record = instruction_data[..=tree_end].to_vec();
+    one_record[tree_end] = 2;
+    assert_eq!(
+        decode_batch(&one_record, DecodeOptions::default()),
+        Err(DecodeError::TruncatedHeader)
+    );
+
+    // Batches have their own entry point and take no checksum trailer
+    assert_eq!(
+        try_huffman_decode_url(&instruction_data).err(),
+        Some(DecodeError::UnsupportedFormat)
+    );
+    assert_eq!(
+        decode_batch(
+            &with_checksum(instruction_data.clone(), ""),
+            DecodeOptions::default()
+        ),
+        Err(DecodeError::UnsupportedFormat)
+    );
+
+    // Errors from the callback stop decoding
+    let mut seen = 0;
+    let result = huffman_decode_batch(&instruction_data, DecodeOptions::STRICT, |_| {
+        seen += 1;
+        Err(DecodeError::InvalidUtf8)
+    });
+    assert_eq!(result, Err(DecodeError::InvalidUtf8));
+    assert_eq!(seen, 1);
+}
+
+#[cfg(feature = "client")]
+#[test]
+pub fn test_decoded_url_as_str_validates_utf8() {
+    let instruction_data = huffman_encode_url("https://🦝👀🍹🌏.net");
+    let decoded = try_huffman_decode_url(&instruction_data).unwrap();
+    assert_eq!(decoded.as_str(), Ok("https://🦝👀🍹🌏.net"));
+
+    // A single 0xff leaf decodes fine at the byte level but is not UTF-8
+    let decoded = try_huffman_decode_url(&[0, 1, 2, 1, 0xff, 0]).unwrap();
+    assert_eq!(decoded.as_bytes(), &[0xff]);
+    assert_eq!(decoded.as_str(), Err(DecodeError::InvalidUtf8));
+}
+
+#[test]
+pub fn test_validate_utf8_edge_cases() {
+    let invalid: [&[u8]; 8] = [
+        &[0x80],                   // lone continuation byte
+        &[0xc0, 0xaf],             // overlong '/'
+        &[0xe0, 0x80, 0xaf],       // overlong three byte form
+        &[0xed, 0xa0, 0x80],       // UTF-16 surrogate
+        &[0xf4, 0x90, 0x80, 0x80], // above U+10FFFF
+        &[0xf0, 0x9f, 0xa6],       // truncated emoji
+        &[0xe2, 0x28, 0xa1],       // bad continuation byte
+        &[0xff],
+    ];
+    for bytes in invalid {
+        assert_eq!(validate_utf8(bytes), Err(DecodeError::InvalidUtf8));
+    }
+    assert_eq!(validate_utf8("a/é/€/🦝".as_bytes()), Ok("a/é/€/🦝"));
+}
+
+proptest::proptest! {
+    #[test]
+    fn test_validate_utf8_matches_core(bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..32)) {
+        proptest::prop_assert_eq!(validate_utf8(&bytes).is_ok(), core::str::from_utf8(&bytes).is_ok());
+    }
+}
+
+#[test]
+pub fn test_decode_error_codes_are_stable() {
+    use pinocchio::program_error::ProgramError;
+
+    let errors = [
+        DecodeError::TruncatedHeader,
+        DecodeError::TruncatedTree,
+        DecodeError::TreeTooLarge,
+        DecodeError::MalformedTree,
+        DecodeError::StackOverflow,
+        DecodeError::BitstreamExhausted,
+        DecodeError::OutputOverflow,
+        DecodeError::InvalidUtf8,
+        DecodeError::UnsupportedFormat,
+        DecodeError::InvalidNodeMarker,
+        DecodeError::TrailingTreeData,
+        DecodeError::DuplicateSymbol,
+        DecodeError::NonZeroPadding,
+        DecodeError::TrailingData,
+        DecodeError::InvalidCodeTable,
+        DecodeError::InvalidSharedTable,
+        DecodeError::ChecksumMismatch,
+        DecodeError::RedundantEscape,
+        DecodeError::NeedlesslyWide,
+    ];
+    for (code, error) in errors.into_iter().enumerate() {
+        assert_eq!(ProgramError::from(error), ProgramError::Custom(code as u32));
+    }
+}